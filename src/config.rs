@@ -1,17 +1,128 @@
 use std::env;
+use std::str::FromStr;
+
+use crate::tiktok::proxy_pool::Rotation;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub port: u16,
+    /// Upstream TikTok origin, overridable to point at a mirror or mock server
+    pub tiktok_base_url: String,
+    /// Retries after the first attempt for 429/5xx and network errors
+    pub retry_max: u32,
+    pub retry_base_delay_ms: u64,
+    /// Consecutive failures before an upstream endpoint's circuit opens
+    pub breaker_threshold: u32,
+    pub breaker_cooldown_secs: u64,
+    /// Outbound proxies as `[region=]url`, e.g. `socks5h://127.0.0.1:9050`
+    pub upstream_proxies: Vec<String>,
+    pub proxy_rotation: Rotation,
+    /// `host_suffix=region` rules sending matching hosts through that region's proxies
+    pub proxy_region_routes: Vec<String>,
+    /// Consecutive connection failures before a proxy is ejected
+    pub proxy_max_failures: u32,
+    /// How long a proxy blocked by TikTok stays out, whatever the health check says
+    pub proxy_block_cooldown_secs: u64,
+    pub proxy_health_check_secs: u64,
+    pub proxy_health_check_url: String,
+    /// Independent upstream cookie sessions, 0 sends requests cookieless
+    pub session_pool_size: usize,
+    /// How long a session's cookies are trusted before it visits the home page again
+    pub session_max_age_secs: u64,
+    /// Seconds parsed pages stay fresh, per kind
+    pub cache_user_ttl_secs: u64,
+    pub cache_video_ttl_secs: u64,
+    pub cache_tag_ttl_secs: u64,
+    /// Seconds past the TTL an entry is still served while it refreshes in the background
+    pub cache_stale_secs: u64,
+    pub cache_not_found_ttl_secs: u64,
+    /// Entries kept per kind, 0 disables caching
+    pub cache_max_entries: usize,
+    /// Directory for cached media, unset disables the disk cache
+    pub media_cache_dir: Option<String>,
+    pub media_cache_max_bytes: u64,
+    /// Larger responses are streamed without being stored
+    pub media_cache_max_object_bytes: u64,
+    /// Also store videos up to the object size limit, not just images
+    pub media_cache_videos: bool,
+    /// Domains (and their subdomains) the media proxy may fetch from
+    pub media_allowed_hosts: Vec<String>,
+    /// Largest file `/proxy` relays
+    pub media_max_bytes: u64,
+    /// HMAC key for `/proxy` links, random per process when unset
+    pub proxy_secret: Option<String>,
+    /// Secret in use before the last rotation, honoured for `proxy_secret_grace_secs` after startup
+    pub proxy_previous_secret: Option<String>,
+    pub proxy_secret_grace_secs: u64,
+    /// How long a signed `/proxy` link stays valid
+    pub proxy_url_ttl_secs: u64,
 }
 
 impl Config {
     pub fn from_env() -> Self {
         Self {
-            port: env::var("PORT")
-                .unwrap_or_else(|_| "3000".to_string())
-                .parse()
-                .expect("PORT must be a number"),
+            port: parse_var("PORT", "3000"),
+            tiktok_base_url: env::var("TIKTOK_BASE_URL")
+                .unwrap_or_else(|_| "https://www.tiktok.com".to_string()),
+            retry_max: parse_var("RETRY_MAX", "2"),
+            retry_base_delay_ms: parse_var("RETRY_BASE_DELAY_MS", "250"),
+            breaker_threshold: parse_var("BREAKER_THRESHOLD", "5"),
+            breaker_cooldown_secs: parse_var("BREAKER_COOLDOWN_SECS", "30"),
+            upstream_proxies: list_var("UPSTREAM_PROXIES"),
+            proxy_rotation: Rotation::parse(&env::var("PROXY_ROTATION").unwrap_or_else(|_| "request".to_string()))
+                .expect("PROXY_ROTATION must be 'request' or 'sticky'"),
+            proxy_region_routes: list_var("PROXY_REGION_ROUTES"),
+            proxy_max_failures: parse_var("PROXY_MAX_FAILURES", "3"),
+            proxy_block_cooldown_secs: parse_var("PROXY_BLOCK_COOLDOWN_SECS", "1800"),
+            proxy_health_check_secs: parse_var("PROXY_HEALTH_CHECK_SECS", "60"),
+            proxy_health_check_url: env::var("PROXY_HEALTH_CHECK_URL")
+                .unwrap_or_else(|_| "https://www.tiktok.com/robots.txt".to_string()),
+            session_pool_size: parse_var("SESSION_POOL_SIZE", "4"),
+            session_max_age_secs: parse_var("SESSION_MAX_AGE_SECS", "3600"),
+            cache_user_ttl_secs: parse_var("CACHE_USER_TTL_SECS", "300"),
+            cache_video_ttl_secs: parse_var("CACHE_VIDEO_TTL_SECS", "900"),
+            cache_tag_ttl_secs: parse_var("CACHE_TAG_TTL_SECS", "300"),
+            cache_stale_secs: parse_var("CACHE_STALE_SECS", "600"),
+            cache_not_found_ttl_secs: parse_var("CACHE_NOT_FOUND_TTL_SECS", "60"),
+            cache_max_entries: parse_var("CACHE_MAX_ENTRIES", "1000"),
+            media_cache_dir: env::var("MEDIA_CACHE_DIR").ok().filter(|dir| !dir.is_empty()),
+            media_cache_max_bytes: parse_var("MEDIA_CACHE_MAX_BYTES", "1073741824"),
+            media_cache_max_object_bytes: parse_var("MEDIA_CACHE_MAX_OBJECT_BYTES", "8388608"),
+            media_cache_videos: flag_var("MEDIA_CACHE_VIDEOS"),
+            media_allowed_hosts: list_var("MEDIA_ALLOWED_HOSTS"),
+            media_max_bytes: parse_var("MEDIA_MAX_BYTES", "209715200"),
+            proxy_secret: env::var("PROXY_SECRET").ok().filter(|secret| !secret.is_empty()),
+            proxy_previous_secret: env::var("PROXY_PREVIOUS_SECRET").ok().filter(|secret| !secret.is_empty()),
+            proxy_secret_grace_secs: parse_var("PROXY_SECRET_GRACE_SECS", "86400"),
+            proxy_url_ttl_secs: parse_var("PROXY_URL_TTL_SECS", "21600"),
         }
     }
 }
+
+fn parse_var<T: FromStr>(key: &str, default: &str) -> T {
+    env::var(key)
+        .unwrap_or_else(|_| default.to_string())
+        .parse()
+        .unwrap_or_else(|_| panic!("{} must be a number", key))
+}
+
+/// `true`/`1`/`yes` enables, anything else or unset disables
+fn flag_var(key: &str) -> bool {
+    env::var(key)
+        .map(|value| matches!(value.to_ascii_lowercase().as_str(), "true" | "1" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Comma separated list, empty when unset
+fn list_var(key: &str) -> Vec<String> {
+    env::var(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -1,10 +1,14 @@
+use askama::MarkupDisplay;
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{Html, IntoResponse, Response},
 };
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+use crate::signing::SignatureError;
+use crate::tiktok::challenge::ChallengeKind;
+
+#[derive(Error, Debug, Clone)]
 pub enum AppError {
     #[error("TikTok content not found")]
     NotFound,
@@ -15,9 +19,30 @@ pub enum AppError {
     #[error("Failed to parse TikTok response")]
     ParseError,
     
+    #[error("Could not read TikTok's {extractor} ({reason}). TikTok may have changed their page structure.")]
+    ExtractionFailed { extractor: &'static str, reason: String },
+    
+    #[error("TikTok is unavailable or rate limiting this instance, please try again later")]
+    RateLimited { retry_after: Option<u64> },
+    
+    #[error("TikTok answered with a {0} instead of content. This instance is switching to a fresh upstream session, please try again shortly.")]
+    UpstreamChallenge(ChallengeKind),
+    
+    #[error("This instance is busy, please try again shortly")]
+    Busy,
+    
     #[error("Invalid URL format")]
     InvalidUrl,
     
+    #[error("Refusing to proxy this media: {0}")]
+    UnsafeMedia(&'static str),
+    
+    #[error("This image can't be converted to {0}")]
+    Unconvertible(&'static str),
+    
+    #[error("This media link is {0}. Reload the page it came from to get a fresh one.")]
+    BadSignature(SignatureError),
+    
     #[error("Internal server error")]
     Internal,
 }
@@ -28,10 +53,19 @@ impl IntoResponse for AppError {
             AppError::NotFound => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::FetchError(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
             AppError::ParseError => (StatusCode::BAD_GATEWAY, self.to_string()),
+            AppError::ExtractionFailed { .. } => (StatusCode::BAD_GATEWAY, self.to_string()),
+            AppError::RateLimited { .. } => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            AppError::UpstreamChallenge(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            AppError::Busy => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
             AppError::InvalidUrl => (StatusCode::BAD_REQUEST, self.to_string()),
+            AppError::UnsafeMedia(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
+            AppError::Unconvertible(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
+            AppError::BadSignature(_) => (StatusCode::FORBIDDEN, self.to_string()),
             AppError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
         };
 
+        // Messages can carry parts of the request path, like a username
+        let message = MarkupDisplay::new_unsafe(message, askama::Html);
         let html = format!(
             r#"<!DOCTYPE html>
 <html lang="en">
@@ -53,6 +87,18 @@ impl IntoResponse for AppError {
             message
         );
 
-        (status, Html(html)).into_response()
+        let mut response = (status, Html(html)).into_response();
+        
+        match self {
+            AppError::RateLimited { retry_after: Some(secs) } => {
+                response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+            }
+            AppError::Busy => {
+                response.headers_mut().insert(header::RETRY_AFTER, 5.into());
+            }
+            _ => {}
+        }
+        
+        response
     }
 }
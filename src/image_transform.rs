@@ -0,0 +1,194 @@
+use axum::body::Bytes;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageError, ImageReader, Limits};
+use lru::LruCache;
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use crate::error::AppError;
+
+/// Widths and heights `/proxy` resizes to. Kept short so every variant of an
+/// image can be cached and `w=` can't be used to fill the cache.
+pub const ALLOWED_SIZES: [u32; 4] = [96, 200, 360, 720];
+
+pub const ALLOWED_QUALITIES: [u8; 3] = [50, 75, 90];
+
+const DEFAULT_QUALITY: u8 = 75;
+
+/// Largest source image we decode, to keep decompression bombs out
+const MAX_SOURCE_DIMENSION: u32 = 8192;
+const MAX_DECODE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Memory budget for resized images when there is no `MEDIA_CACHE_DIR`
+const MEMORY_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Jpeg,
+    /// Lossless, the only WebP encoder available, so `q` doesn't apply
+    WebP,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            "webp" => Some(OutputFormat::WebP),
+            _ => None,
+        }
+    }
+    
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::WebP => "image/webp",
+        }
+    }
+}
+
+/// Resize and re-encode requested through `w`, `h`, `q` and `fmt`
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub quality: u8,
+    pub format: OutputFormat,
+    /// `fmt` was given, so handing out the original instead won't do
+    pub explicit_format: bool,
+}
+
+impl Transform {
+    /// `None` when no parameter is set; values outside the allowed sets are rejected
+    pub fn from_params(
+        width: Option<u32>,
+        height: Option<u32>,
+        quality: Option<u8>,
+        format: Option<&str>,
+    ) -> Result<Option<Self>, AppError> {
+        if width.is_none() && height.is_none() && quality.is_none() && format.is_none() {
+            return Ok(None);
+        }
+        
+        let size_allowed = |size: Option<u32>| size.is_none_or(|size| ALLOWED_SIZES.contains(&size));
+        if !size_allowed(width) || !size_allowed(height) {
+            return Err(AppError::InvalidUrl);
+        }
+        if quality.is_some_and(|quality| !ALLOWED_QUALITIES.contains(&quality)) {
+            return Err(AppError::InvalidUrl);
+        }
+        let explicit_format = format.is_some();
+        let format = match format {
+            Some(format) => OutputFormat::parse(format).ok_or(AppError::InvalidUrl)?,
+            None => OutputFormat::Jpeg,
+        };
+        
+        Ok(Some(Self {
+            width,
+            height,
+            quality: quality.unwrap_or(DEFAULT_QUALITY),
+            format,
+            explicit_format,
+        }))
+    }
+    
+    /// Distinguishes this variant's cache entry from the original's
+    pub fn variant(&self) -> String {
+        format!(
+            "w{}-h{}-q{}-{}",
+            self.width.unwrap_or(0),
+            self.height.unwrap_or(0),
+            self.quality,
+            self.format.content_type()
+        )
+    }
+    
+    /// Decode, shrink to fit within the requested box and encode. Animated
+    /// images keep their first frame. CPU heavy, run it on a blocking thread.
+    pub fn apply(&self, source: &[u8]) -> Result<Vec<u8>, ImageError> {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+        limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+        limits.max_alloc = Some(MAX_DECODE_BYTES);
+        
+        let mut reader = ImageReader::new(Cursor::new(source)).with_guessed_format()?;
+        reader.limits(limits);
+        let image = reader.decode()?;
+        
+        let image = self.resize(image);
+        
+        let mut out = Vec::new();
+        match self.format {
+            OutputFormat::Jpeg => {
+                let rgb = image.to_rgb8();
+                JpegEncoder::new_with_quality(&mut out, self.quality).encode_image(&rgb)?;
+            }
+            OutputFormat::WebP => {
+                let rgba = DynamicImage::ImageRgba8(image.to_rgba8());
+                rgba.write_with_encoder(WebPEncoder::new_lossless(&mut out))?;
+            }
+        }
+        
+        Ok(out)
+    }
+    
+    /// Never upscales, and keeps the aspect ratio
+    fn resize(&self, image: DynamicImage) -> DynamicImage {
+        let max_width = self.width.unwrap_or(u32::MAX);
+        let max_height = self.height.unwrap_or(u32::MAX);
+        
+        if image.width() <= max_width && image.height() <= max_height {
+            return image;
+        }
+        
+        image.resize(max_width.min(image.width()), max_height.min(image.height()), FilterType::Triangle)
+    }
+}
+
+struct Variants {
+    /// Body and content type by variant key, least recently used first
+    entries: LruCache<String, (Bytes, &'static str)>,
+    total_bytes: usize,
+}
+
+/// Resized images kept in memory, for instances without a disk media cache
+pub struct VariantCache {
+    variants: Mutex<Variants>,
+}
+
+impl Default for VariantCache {
+    fn default() -> Self {
+        Self {
+            variants: Mutex::new(Variants {
+                entries: LruCache::unbounded(),
+                total_bytes: 0,
+            }),
+        }
+    }
+}
+
+impl VariantCache {
+    pub fn get(&self, key: &str) -> Option<(Bytes, &'static str)> {
+        self.variants.lock().unwrap().entries.get(key).cloned()
+    }
+    
+    /// Store a variant, dropping the least recently used ones to stay within budget
+    pub fn insert(&self, key: String, body: Bytes, content_type: &'static str) {
+        if body.len() > MEMORY_CACHE_BYTES / 16 {
+            return;
+        }
+        
+        let mut variants = self.variants.lock().unwrap();
+        variants.total_bytes += body.len();
+        if let Some((_, (old, _))) = variants.entries.push(key, (body, content_type)) {
+            variants.total_bytes -= old.len();
+        }
+        while variants.total_bytes > MEMORY_CACHE_BYTES {
+            match variants.entries.pop_lru() {
+                Some((_, (old, _))) => variants.total_bytes -= old.len(),
+                None => break,
+            }
+        }
+    }
+}
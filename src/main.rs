@@ -1,7 +1,14 @@
 mod config;
 mod error;
+mod image_transform;
+mod media_cache;
+mod media_type;
+mod metrics;
 mod routes;
+mod signing;
+mod state;
 mod tiktok;
+mod zip_stream;
 
 use axum::{
     Router,
@@ -21,9 +28,8 @@ async fn security_headers(
     let mut response = next.run(request).await;
     let headers = response.headers_mut();
     
-    // Strong CSP - blocks all connections to TikTok
-    headers.insert(
-        header::CONTENT_SECURITY_POLICY,
+    // Strong CSP - blocks all connections to TikTok. Proxied media sets its own sandbox policy.
+    headers.entry(header::CONTENT_SECURITY_POLICY).or_insert(
         "default-src 'self'; script-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; media-src 'self'; frame-ancestors 'none'; form-action 'self'"
             .parse()
             .unwrap(),
@@ -61,11 +67,14 @@ async fn main() {
     
     tracing::info!("🦀 RustyTok starting on port {}", config.port);
 
+    let state = state::AppState::from_config(&config);
+
     // Build router
     let app = Router::new()
         .merge(routes::router())
         .nest_service("/static", ServeDir::new("static"))
-        .layer(middleware::from_fn(security_headers));
+        .layer(middleware::from_fn(security_headers))
+        .with_state(state);
 
     // Start server
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
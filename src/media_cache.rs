@@ -0,0 +1,247 @@
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use url::Url;
+
+/// What we know about one cached URL, persisted as `keys/{key}.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedMedia {
+    /// SHA-256 of the body, which is also its file name under `objects/`
+    pub digest: String,
+    pub content_type: String,
+    pub size: u64,
+}
+
+impl CachedMedia {
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.digest)
+    }
+}
+
+struct Index {
+    /// Cache keys in least recently used order
+    keys: LruCache<String, CachedMedia>,
+    /// Reference count per object, identical media behind different URLs is stored once
+    objects: HashMap<String, u32>,
+    total_bytes: u64,
+}
+
+/// Content-addressed on-disk cache of proxied media with a byte budget
+pub struct MediaCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_object_bytes: u64,
+    cache_videos: bool,
+    index: Mutex<Index>,
+}
+
+impl MediaCache {
+    /// Open the cache in `dir`, picking up entries left by a previous run
+    pub fn open(dir: &Path, max_bytes: u64, max_object_bytes: u64, cache_videos: bool) -> io::Result<Self> {
+        for sub in ["objects", "keys", "tmp"] {
+            fs::create_dir_all(dir.join(sub))?;
+        }
+        
+        // Leftovers from interrupted writes
+        for entry in fs::read_dir(dir.join("tmp"))? {
+            let _ = fs::remove_file(entry?.path());
+        }
+        
+        // Oldest first, so the most recently written entries end up most recently used
+        let mut stored = Vec::new();
+        for entry in fs::read_dir(dir.join("keys"))? {
+            let path = entry?.path();
+            let meta = fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<CachedMedia>(&bytes).ok())
+                .filter(|meta| dir.join("objects").join(&meta.digest).is_file());
+            
+            match (path.file_stem().and_then(|s| s.to_str()), meta) {
+                (Some(key), Some(meta)) => {
+                    let modified = fs::metadata(&path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+                    stored.push((modified, key.to_string(), meta));
+                }
+                _ => {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+        stored.sort_by_key(|(modified, _, _)| *modified);
+        
+        let mut index = Index {
+            keys: LruCache::unbounded(),
+            objects: HashMap::new(),
+            total_bytes: 0,
+        };
+        for (_, key, meta) in stored {
+            retain(&mut index, &meta);
+            index.keys.put(key, meta);
+        }
+        
+        // Objects no key points at anymore
+        for entry in fs::read_dir(dir.join("objects"))? {
+            let path = entry?.path();
+            let referenced = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| index.objects.contains_key(name));
+            if !referenced {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        
+        tracing::info!(
+            "Media cache at {} holds {} entries ({} bytes)",
+            dir.display(),
+            index.keys.len(),
+            index.total_bytes
+        );
+        
+        let cache = Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            max_object_bytes,
+            cache_videos,
+            index: Mutex::new(index),
+        };
+        cache.evict();
+        Ok(cache)
+    }
+    
+    /// Cache key for a CDN URL, ignoring the signature query which changes between page loads
+    pub fn key_for(url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        let stable = format!("{}{}", url.host_str()?, url.path());
+        Some(hex_digest(stable.as_bytes()))
+    }
+    
+    /// Key for a derived copy of `key`, e.g. a resized image
+    pub fn variant_key(key: &str, variant: &str) -> String {
+        hex_digest(format!("{}/{}", key, variant).as_bytes())
+    }
+    
+    /// Whether a response with these headers should be stored
+    pub fn should_cache(&self, content_type: &str, content_length: Option<u64>) -> bool {
+        let kind_allowed = content_type.starts_with("image/")
+            || (self.cache_videos && content_type.starts_with("video/"));
+        
+        kind_allowed && content_length.is_some_and(|len| len <= self.max_object_bytes)
+    }
+    
+    pub fn object_path(&self, media: &CachedMedia) -> PathBuf {
+        self.dir.join("objects").join(&media.digest)
+    }
+    
+    /// Cached media for `key`, marking it as recently used
+    pub fn lookup(&self, key: &str) -> Option<CachedMedia> {
+        let mut index = self.index.lock().unwrap();
+        let media = index.keys.get(key)?.clone();
+        
+        if self.object_path(&media).is_file() {
+            Some(media)
+        } else {
+            // Removed behind our back
+            drop(index);
+            self.remove(key);
+            None
+        }
+    }
+    
+    /// Store `body` under `key`, written to a temp file and renamed into place
+    pub async fn insert(&self, key: &str, content_type: &str, body: &[u8]) -> io::Result<CachedMedia> {
+        let media = CachedMedia {
+            digest: hex_digest(body),
+            content_type: content_type.to_string(),
+            size: body.len() as u64,
+        };
+        
+        let object_path = self.object_path(&media);
+        if !tokio::fs::try_exists(&object_path).await? {
+            self.write_atomic(&object_path, body).await?;
+        }
+        
+        let meta = serde_json::to_vec(&media).map_err(io::Error::other)?;
+        self.write_atomic(&self.dir.join("keys").join(format!("{}.json", key)), &meta).await?;
+        
+        {
+            let mut index = self.index.lock().unwrap();
+            retain(&mut index, &media);
+            
+            if let Some(previous) = index.keys.put(key.to_string(), media.clone()) {
+                release(&mut index, &previous, &self.dir);
+            }
+        }
+        
+        self.evict();
+        Ok(media)
+    }
+    
+    async fn write_atomic(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        let tmp = self.dir.join("tmp").join(format!("{:016x}", rand::random::<u64>()));
+        tokio::fs::write(&tmp, bytes).await?;
+        
+        if let Err(e) = tokio::fs::rename(&tmp, path).await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(e);
+        }
+        Ok(())
+    }
+    
+    fn remove(&self, key: &str) {
+        let mut index = self.index.lock().unwrap();
+        if let Some(media) = index.keys.pop(key) {
+            release(&mut index, &media, &self.dir);
+            let _ = fs::remove_file(self.dir.join("keys").join(format!("{}.json", key)));
+        }
+    }
+    
+    /// Drop least recently used entries until we're within the byte budget
+    fn evict(&self) {
+        let mut index = self.index.lock().unwrap();
+        
+        while index.total_bytes > self.max_bytes {
+            let Some((key, media)) = index.keys.pop_lru() else {
+                break;
+            };
+            tracing::debug!("Evicting cached media {}", key);
+            release(&mut index, &media, &self.dir);
+            let _ = fs::remove_file(self.dir.join("keys").join(format!("{}.json", key)));
+        }
+    }
+}
+
+/// Add a reference to an object, counting its size the first time
+fn retain(index: &mut Index, media: &CachedMedia) {
+    let refs = index.objects.entry(media.digest.clone()).or_insert(0);
+    *refs += 1;
+    if *refs == 1 {
+        index.total_bytes += media.size;
+    }
+}
+
+/// Drop one reference to an object, deleting it once nothing uses it
+fn release(index: &mut Index, media: &CachedMedia, dir: &Path) {
+    let Some(refs) = index.objects.get_mut(&media.digest) else {
+        return;
+    };
+    
+    *refs -= 1;
+    if *refs == 0 {
+        index.objects.remove(&media.digest);
+        index.total_bytes = index.total_bytes.saturating_sub(media.size);
+        let _ = fs::remove_file(dir.join("objects").join(&media.digest));
+    }
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,172 @@
+/// Bytes needed to recognise every format below
+pub const SNIFF_LEN: usize = 16;
+
+/// Declared types that don't say what the file is, so sniffing alone decides
+const GENERIC_TYPES: [&str; 2] = ["application/octet-stream", "binary/octet-stream"];
+
+/// `type/subtype` without parameters, lowercased
+pub fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Image, video or audio, minus SVG which can carry scripts
+pub fn is_media_type(content_type: &str) -> bool {
+    let essence = essence(content_type);
+    let media = essence.starts_with("image/") || essence.starts_with("video/") || essence.starts_with("audio/");
+    media && essence != "image/svg+xml"
+}
+
+/// Whether an upstream `Content-Type` may be an image, pending a look at the bytes
+pub fn may_be_image(declared: &str) -> bool {
+    let essence = essence(declared);
+    (essence.starts_with("image/") && essence != "image/svg+xml") || GENERIC_TYPES.contains(&essence.as_str())
+}
+
+/// Whether an upstream `Content-Type` may be proxied, pending a look at the bytes
+pub fn is_acceptable(declared: &str) -> bool {
+    is_media_type(declared) || GENERIC_TYPES.contains(&essence(declared).as_str())
+}
+
+/// Media type recognised from the first bytes of a file
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, magic: &[u8]| bytes.get(offset..offset + magic.len()) == Some(magic);
+
+    if at(0, &[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if at(0, b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        Some("image/gif")
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        Some("image/webp")
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        Some("audio/wav")
+    } else if at(4, b"ftyp") {
+        // ISO base media, the brand tells images and audio apart from video
+        Some(match bytes.get(8..12) {
+            Some(b"avif") | Some(b"avis") => "image/avif",
+            Some(b"heic") | Some(b"heix") | Some(b"mif1") => "image/heic",
+            Some(b"M4A ") => "audio/mp4",
+            _ => "video/mp4",
+        })
+    } else if at(0, &[0x1a, 0x45, 0xdf, 0xa3]) {
+        Some("video/webm")
+    } else if at(0, b"OggS") {
+        Some("audio/ogg")
+    } else if at(0, b"ID3") || (bytes.len() >= 2 && bytes[0] == 0xff && bytes[1] & 0xe0 == 0xe0) {
+        Some("audio/mpeg")
+    } else {
+        None
+    }
+}
+
+/// Check the bytes against the declared type. Returns the type to serve,
+/// or `None` when the content isn't recognisable media or contradicts a specific declared type.
+pub fn confirm(declared: &str, head: &[u8]) -> Option<&'static str> {
+    let sniffed = sniff(head)?;
+    let declared = essence(declared);
+
+    if GENERIC_TYPES.contains(&declared.as_str()) {
+        return Some(sniffed);
+    }
+
+    // The CDN often mislabels the exact format, but never the kind of media
+    let kind = |content_type: &str| content_type.split('/').next().map(str::to_string);
+    (kind(&declared) == kind(sniffed)).then_some(sniffed)
+}
+
+/// File extension for `Content-Disposition`
+pub fn extension(content_type: &str) -> &'static str {
+    match essence(content_type).as_str() {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        "image/heic" => "heic",
+        "video/webm" => "webm",
+        "audio/mpeg" => "mp3",
+        "audio/mp4" => "m4a",
+        "audio/ogg" => "ogg",
+        "audio/wav" => "wav",
+        _ => "mp4",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JPEG: &[u8] = b"\xff\xd8\xff\xe0\0\x10JFIF\0\x01\x01\0\0\x01";
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    const WEBP: &[u8] = b"RIFF\x1c\x06\0\0WEBPVP8L";
+    const HTML: &[u8] = b"<!DOCTYPE html><html>";
+    const SVG: &[u8] = b"<svg xmlns=\"http://www.w3.org/2000/svg\">";
+
+    fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+        [b"\0\0\0\x18ftyp".as_slice(), brand, b"\0\0\0\0"].concat()
+    }
+
+    #[test]
+    fn ftyp_brands() {
+        let cases: [(&[u8; 4], &str); 7] = [
+            (b"avif", "image/avif"),
+            (b"avis", "image/avif"),
+            (b"heic", "image/heic"),
+            (b"mif1", "image/heic"),
+            (b"M4A ", "audio/mp4"),
+            (b"isom", "video/mp4"),
+            (b"mp42", "video/mp4"),
+        ];
+
+        for (brand, expected) in cases {
+            assert_eq!(sniff(&ftyp(brand)), Some(expected), "{:?}", brand);
+        }
+    }
+
+    #[test]
+    fn confirm_declared_types() {
+        let heic = ftyp(b"heic");
+        let mp4 = ftyp(b"isom");
+        let cases: [(&str, &[u8], Option<&str>); 14] = [
+            // Markup is never media, whatever it claims to be
+            ("image/jpeg", HTML, None),
+            ("image/svg+xml", SVG, None),
+            ("image/png", SVG, None),
+            ("application/octet-stream", HTML, None),
+            ("text/html", JPEG, None),
+            // The CDN mixes up formats within a kind
+            ("image/webp", JPEG, Some("image/jpeg")),
+            ("image/jpeg", PNG, Some("image/png")),
+            ("image/png; charset=binary", WEBP, Some("image/webp")),
+            ("IMAGE/JPEG", JPEG, Some("image/jpeg")),
+            // But not the kind itself
+            ("video/mp4", JPEG, None),
+            ("image/jpeg", &mp4, None),
+            // Generic types take whatever the bytes are
+            ("application/octet-stream", &heic, Some("image/heic")),
+            ("binary/octet-stream", &mp4, Some("video/mp4")),
+            ("application/octet-stream", PNG, Some("image/png")),
+        ];
+
+        for (declared, head, expected) in cases {
+            assert_eq!(confirm(declared, head), expected, "{} {:?}", declared, head);
+        }
+    }
+
+    #[test]
+    fn declared_types_before_sniffing() {
+        assert!(may_be_image("image/jpeg"));
+        assert!(may_be_image("application/octet-stream"));
+        assert!(!may_be_image("image/svg+xml"));
+        assert!(!may_be_image("video/mp4"));
+        assert!(is_acceptable("video/mp4; codecs=avc1"));
+        assert!(!is_acceptable("image/svg+xml"));
+        assert!(!is_acceptable("text/html"));
+    }
+}
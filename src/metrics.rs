@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::tiktok::challenge::ChallengeKind;
+
+static CHALLENGES_CAPTCHA: AtomicU64 = AtomicU64::new(0);
+static CHALLENGES_WAF: AtomicU64 = AtomicU64::new(0);
+static CHALLENGES_LOGIN_WALL: AtomicU64 = AtomicU64::new(0);
+
+fn challenge_counter(kind: ChallengeKind) -> &'static AtomicU64 {
+    match kind {
+        ChallengeKind::Captcha => &CHALLENGES_CAPTCHA,
+        ChallengeKind::Waf => &CHALLENGES_WAF,
+        ChallengeKind::LoginWall => &CHALLENGES_LOGIN_WALL,
+    }
+}
+
+pub fn record_challenge(kind: ChallengeKind) {
+    challenge_counter(kind).fetch_add(1, Ordering::Relaxed);
+}
+
+/// Prometheus text exposition of all counters
+pub fn render() -> String {
+    let mut out = String::from(
+        "# HELP rustytok_upstream_challenges_total Challenge pages served by TikTok instead of content\n\
+         # TYPE rustytok_upstream_challenges_total counter\n",
+    );
+    
+    for kind in [ChallengeKind::Captcha, ChallengeKind::Waf, ChallengeKind::LoginWall] {
+        out.push_str(&format!(
+            "rustytok_upstream_challenges_total{{kind=\"{}\"}} {}\n",
+            kind.metric_label(),
+            challenge_counter(kind).load(Ordering::Relaxed)
+        ));
+    }
+    
+    out
+}
@@ -7,6 +7,9 @@ use axum::{
 };
 use serde::Deserialize;
 
+use crate::state::AppState;
+use crate::tiktok::link::TikTokLink;
+
 #[derive(Template)]
 #[template(path = "home.html")]
 struct HomeTemplate {
@@ -21,26 +24,8 @@ pub struct SearchQuery {
 async fn home(Query(params): Query<SearchQuery>) -> impl IntoResponse {
     // If there's a search query, redirect to appropriate page
     if let Some(ref q) = params.q {
-        let q = q.trim();
-        
-        // Handle different input types
-        if q.starts_with('@') {
-            // Username
-            return Redirect::to(&format!("/{}", q)).into_response();
-        } else if q.starts_with('#') {
-            // Hashtag
-            return Redirect::to(&format!("/tag/{}", &q[1..])).into_response();
-        } else if q.contains("tiktok.com") {
-            // TikTok URL - parse and redirect
-            if let Some(path) = parse_tiktok_url(q) {
-                return Redirect::to(&path).into_response();
-            }
-        } else if q.chars().all(|c| c.is_ascii_digit()) {
-            // Video ID
-            return Redirect::to(&format!("/video/{}", q)).into_response();
-        } else {
-            // Assume username without @
-            return Redirect::to(&format!("/@{}", q)).into_response();
+        if let Some(link) = search_link(q) {
+            return Redirect::to(&link.to_local_path()).into_response();
         }
     }
     
@@ -48,45 +33,32 @@ async fn home(Query(params): Query<SearchQuery>) -> impl IntoResponse {
     Html(template.render().unwrap()).into_response()
 }
 
-fn parse_tiktok_url(url: &str) -> Option<String> {
-    // Handle various TikTok URL formats
-    // https://www.tiktok.com/@username
-    // https://www.tiktok.com/@username/video/1234567890
-    // https://vm.tiktok.com/XXXXX
-    // https://www.tiktok.com/t/XXXXX
+/// Interpret the search box input
+fn search_link(q: &str) -> Option<TikTokLink> {
+    let q = q.trim();
     
-    if url.contains("/@") {
-        // User or video URL
-        if let Some(at_pos) = url.find("/@") {
-            let path = &url[at_pos..];
-            // Remove query params if any
-            let path = path.split('?').next().unwrap_or(path);
-            return Some(path.to_string());
-        }
-    } else if url.contains("/video/") {
-        // Direct video URL
-        if let Some(video_pos) = url.find("/video/") {
-            let video_id = &url[video_pos + 7..];
-            let video_id = video_id.split('?').next().unwrap_or(video_id);
-            let video_id = video_id.split('/').next().unwrap_or(video_id);
-            return Some(format!("/video/{}", video_id));
-        }
-    } else if url.contains("vm.tiktok.com") || url.contains("/t/") {
-        // Short URL - we'll handle redirect on the server
-        return Some(format!("/redirect?url={}", urlencoding::encode(url)));
-    } else if url.contains("/tag/") || url.contains("/discover/") {
-        // Tag URL
-        if let Some(tag_pos) = url.find("/tag/") {
-            let tag = &url[tag_pos + 5..];
-            let tag = tag.split('?').next().unwrap_or(tag);
-            return Some(format!("/tag/{}", tag));
-        }
+    // Handle different input types
+    if q.is_empty() {
+        None
+    } else if let Some(username) = q.strip_prefix('@') {
+        // Username
+        Some(TikTokLink::User(username.to_string()))
+    } else if let Some(tag) = q.strip_prefix('#') {
+        // Hashtag
+        Some(TikTokLink::Tag(tag.to_string()))
+    } else if q.contains("tiktok.com") || q.contains("://") {
+        // TikTok URL or app deep link
+        TikTokLink::parse(q)
+    } else if q.chars().all(|c| c.is_ascii_digit()) {
+        // Video ID
+        Some(TikTokLink::Video { username: None, id: q.to_string() })
+    } else {
+        // Assume username without @
+        Some(TikTokLink::User(q.to_string()))
     }
-    
-    None
 }
 
-pub fn router() -> Router {
+pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(home))
 }
@@ -0,0 +1,316 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderValue},
+    response::Response,
+    routing::get,
+    Router,
+};
+
+use futures_util::stream;
+use serde::Deserialize;
+use std::io;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::Semaphore;
+
+use crate::error::AppError;
+use crate::media_cache::MediaCache;
+use crate::media_type;
+use crate::signing;
+use crate::state::AppState;
+use crate::tiktok::types::VideoInfo;
+use crate::zip_stream::ZipStream;
+use super::proxy::{fetch_image, serve_media, Served};
+use super::validate_username;
+
+/// Which CDN URL a stable media link stands for
+#[derive(Debug, Clone, Copy)]
+enum MediaKind {
+    Video,
+    Cover,
+    Avatar,
+    /// A download variant by index, or the preferred one
+    Download(Option<usize>),
+    /// One photo of a photo post, by index
+    Image(usize),
+    /// The sound under a photo post
+    Audio,
+}
+
+impl MediaKind {
+    /// What `/media` links to this kind are signed as. Every photo of a post
+    /// and every download variant share a signature.
+    fn name(self) -> &'static str {
+        match self {
+            MediaKind::Video => "video",
+            MediaKind::Cover => "cover",
+            MediaKind::Avatar => "avatar",
+            MediaKind::Download(_) => "download",
+            MediaKind::Image(_) => "image",
+            MediaKind::Audio => "audio",
+        }
+    }
+    
+    /// Current CDN URL, from the page cache or a fresh fetch
+    async fn lookup(self, state: &AppState, id: &str) -> Result<String, AppError> {
+        Ok(match self {
+            MediaKind::Video => state.source.fetch_video(id).await?.video_url,
+            MediaKind::Cover => state.source.fetch_video(id).await?.thumbnail_url,
+            MediaKind::Avatar => state.source.fetch_profile(id).await?.avatar_url,
+            MediaKind::Download(index) => {
+                let video = state.source.fetch_video(id).await?;
+                let variant = match index {
+                    Some(index) => Some(video.variants.get(index).ok_or(AppError::NotFound)?),
+                    None => video.preferred_variant(),
+                };
+                variant.map(|variant| variant.url.clone()).unwrap_or(video.video_url)
+            }
+            MediaKind::Image(index) => {
+                let mut video = state.source.fetch_video(id).await?;
+                if index >= video.images.len() {
+                    return Err(AppError::NotFound);
+                }
+                video.images.swap_remove(index).url
+            }
+            MediaKind::Audio => state.source.fetch_video(id).await?.audio_url,
+        })
+    }
+    
+    fn invalidate(self, state: &AppState, id: &str) {
+        match self {
+            MediaKind::Video
+            | MediaKind::Cover
+            | MediaKind::Download(_)
+            | MediaKind::Image(_)
+            | MediaKind::Audio => state.source.invalidate_video(id),
+            MediaKind::Avatar => state.source.invalidate_user(id),
+        }
+    }
+}
+
+/// Serve the media behind `id`, refetching the page once if the CDN URL we had was rejected
+async fn serve_stable(state: &AppState, kind: MediaKind, id: &str, mut request: Request) -> Result<Response, AppError> {
+    for attempt in 0..2 {
+        if attempt > 0 {
+            tracing::debug!("CDN rejected {:?} URL for {}, refreshing", kind, id);
+            kind.invalidate(state, id);
+        }
+        
+        let url = kind.lookup(state, id).await?;
+        if url.is_empty() {
+            return Err(AppError::NotFound);
+        }
+        let url = state.media_policy.parse(&url)?;
+        
+        match serve_media(state, &url, request).await? {
+            Served::Response(response) => return Ok(response),
+            Served::Forbidden(returned) => request = returned,
+        }
+    }
+    
+    Err(AppError::NotFound)
+}
+
+#[derive(Deserialize)]
+struct SignedQuery {
+    sig: Option<String>,
+}
+
+/// Only links from pages we rendered, so the instance can't be used as an open CDN proxy
+fn verify(kind: &str, id: &str, signature: Option<&str>) -> Result<(), AppError> {
+    signing::signer()
+        .verify_media(kind, id, signature)
+        .map_err(AppError::BadSignature)
+}
+
+fn validate_video_id(video_id: &str) -> Result<(), AppError> {
+    if video_id.is_empty() || !video_id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(AppError::InvalidUrl);
+    }
+    Ok(())
+}
+
+async fn media_video(
+    State(state): State<AppState>,
+    Path(video_id): Path<String>,
+    Query(query): Query<SignedQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    validate_video_id(&video_id)?;
+    verify(MediaKind::Video.name(), &video_id, query.sig.as_deref())?;
+    serve_stable(&state, MediaKind::Video, &video_id, request).await
+}
+
+async fn media_cover(
+    State(state): State<AppState>,
+    Path(video_id): Path<String>,
+    Query(query): Query<SignedQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    validate_video_id(&video_id)?;
+    verify(MediaKind::Cover.name(), &video_id, query.sig.as_deref())?;
+    serve_stable(&state, MediaKind::Cover, &video_id, request).await
+}
+
+#[derive(Deserialize)]
+struct DownloadQuery {
+    variant: Option<usize>,
+    sig: Option<String>,
+}
+
+/// The video as an attachment named after its author, unwatermarked when TikTok offers it
+async fn media_download(
+    State(state): State<AppState>,
+    Path(video_id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    validate_video_id(&video_id)?;
+    let kind = MediaKind::Download(query.variant);
+    verify(kind.name(), &video_id, query.sig.as_deref())?;
+    let filename = state.source.fetch_video(&video_id).await?.download_filename();
+    
+    let mut response = serve_stable(&state, kind, &video_id, request).await?;
+    if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename)) {
+        response.headers_mut().insert(header::CONTENT_DISPOSITION, value);
+    }
+    Ok(response)
+}
+
+async fn media_image(
+    State(state): State<AppState>,
+    Path((video_id, index)): Path<(String, usize)>,
+    Query(query): Query<SignedQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    validate_video_id(&video_id)?;
+    verify(MediaKind::Image(index).name(), &video_id, query.sig.as_deref())?;
+    serve_stable(&state, MediaKind::Image(index), &video_id, request).await
+}
+
+async fn media_audio(
+    State(state): State<AppState>,
+    Path(video_id): Path<String>,
+    Query(query): Query<SignedQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    validate_video_id(&video_id)?;
+    verify(MediaKind::Audio.name(), &video_id, query.sig.as_deref())?;
+    serve_stable(&state, MediaKind::Audio, &video_id, request).await
+}
+
+/// Photo zips being sent at once
+static ZIP_SLOTS: Semaphore = Semaphore::const_new(4);
+
+/// All photos of one post together, each is also held to the image size cap
+const IMAGES_ZIP_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Every photo of a photo post in one zip, `author_id_1.jpg` onwards
+async fn media_download_images(
+    State(state): State<AppState>,
+    Path(video_id): Path<String>,
+    Query(query): Query<SignedQuery>,
+) -> Result<Response, AppError> {
+    validate_video_id(&video_id)?;
+    verify("images", &video_id, query.sig.as_deref())?;
+    let permit = ZIP_SLOTS.try_acquire().map_err(|_| AppError::Busy)?;
+    
+    let mut video = state.source.fetch_video(&video_id).await?;
+    let first = video.images.first().ok_or(AppError::NotFound)?;
+    
+    // The CDN URLs may have expired since the page was cached, the first photo tells
+    let first = match fetch_photo(&state, &first.url).await {
+        Err(AppError::NotFound) => {
+            tracing::debug!("CDN rejected photo URLs for {}, refreshing", video_id);
+            state.source.invalidate_video(&video_id);
+            video = state.source.fetch_video(&video_id).await?;
+            let first = video.images.first().ok_or(AppError::NotFound)?;
+            fetch_photo(&state, &first.url).await?
+        }
+        result => result?,
+    };
+    
+    let disposition = format!("attachment; filename=\"{}_images.zip\"", video.download_stem());
+    
+    let (tx, rx) = mpsc::channel(2);
+    tokio::spawn(async move {
+        let _permit = permit;
+        if let Err(e) = write_image_zip(&state, &video, first, &tx).await {
+            tracing::warn!("Photo zip for {} failed: {}", video.id, e);
+            let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+        }
+    });
+    let body = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+    
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(header::CONTENT_DISPOSITION, disposition)
+        .body(Body::from_stream(body))
+        .unwrap())
+}
+
+async fn fetch_photo(state: &AppState, url: &str) -> Result<(Bytes, &'static str), AppError> {
+    let url = state.media_policy.parse(url)?;
+    let key = MediaCache::key_for(url.as_str());
+    fetch_image(state, &url, state.media_cache.as_deref().zip(key.as_deref())).await
+}
+
+/// Send the photos one at a time as an uncompressed zip, they're JPEG or WebP already
+async fn write_image_zip(
+    state: &AppState,
+    video: &VideoInfo,
+    first: (Bytes, &'static str),
+    tx: &Sender<io::Result<Bytes>>,
+) -> Result<(), AppError> {
+    let mut zip = ZipStream::default();
+    let mut total: u64 = 0;
+    let mut fetched = Some(first);
+    
+    for (index, image) in video.images.iter().enumerate() {
+        let (body, content_type) = match fetched.take() {
+            Some(photo) => photo,
+            None => fetch_photo(state, &image.url).await?,
+        };
+        
+        total += body.len() as u64;
+        if total > IMAGES_ZIP_MAX_BYTES {
+            return Err(AppError::UnsafeMedia("file is too large"));
+        }
+        
+        let name = format!("{}_{}.{}", video.download_stem(), index + 1, media_type::extension(content_type));
+        for chunk in zip.entry(&name, body).map_err(|_| AppError::Internal)? {
+            if tx.send(Ok(chunk)).await.is_err() {
+                // The client went away
+                return Ok(());
+            }
+        }
+    }
+    
+    let end = zip.finish().map_err(|_| AppError::Internal)?;
+    let _ = tx.send(Ok(end)).await;
+    Ok(())
+}
+
+async fn media_avatar(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Query(query): Query<SignedQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    let username = username.trim_start_matches('@');
+    validate_username(username)?;
+    verify(MediaKind::Avatar.name(), username, query.sig.as_deref())?;
+    serve_stable(&state, MediaKind::Avatar, username, request).await
+}
+
+/// Links that stay valid after the CDN URLs inside a page have expired
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/media/video/:video_id", get(media_video))
+        .route("/media/cover/:video_id", get(media_cover))
+        .route("/media/image/:video_id/:index", get(media_image))
+        .route("/media/audio/:video_id", get(media_audio))
+        .route("/media/download/:video_id", get(media_download))
+        .route("/media/download/:video_id/images", get(media_download_images))
+        .route("/media/avatar/:username", get(media_avatar))
+}
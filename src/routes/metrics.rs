@@ -0,0 +1,22 @@
+use axum::{
+    http::header,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+
+use crate::metrics;
+use crate::state::AppState;
+
+/// Counters in Prometheus text format
+async fn get_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(),
+    )
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/metrics", get(get_metrics))
+}
@@ -2,15 +2,53 @@ mod home;
 mod user;
 mod video;
 mod tag;
+mod music;
 mod proxy;
+mod media;
+mod redirect;
+mod metrics;
 
 use axum::Router;
+use serde::Deserialize;
 
-pub fn router() -> Router {
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::tiktok::link;
+
+/// `?cursor=` query for paginated video grids
+#[derive(Deserialize)]
+pub struct PageQuery {
+    cursor: Option<String>,
+}
+
+impl PageQuery {
+    /// Item list cursors are numeric, anything else is rejected
+    pub fn cursor(&self) -> Result<Option<&str>, AppError> {
+        match self.cursor.as_deref() {
+            None | Some("") | Some("0") => Ok(None),
+            Some(c) if c.chars().all(|ch| ch.is_ascii_digit()) => Ok(Some(c)),
+            Some(_) => Err(AppError::InvalidUrl),
+        }
+    }
+}
+
+/// Same rule the link parser applies to `@name` segments
+pub fn validate_username(username: &str) -> Result<(), AppError> {
+    if !link::is_username(username) {
+        return Err(AppError::InvalidUrl);
+    }
+    Ok(())
+}
+
+pub fn router() -> Router<AppState> {
     Router::new()
         .merge(home::router())
         .merge(user::router())
         .merge(video::router())
         .merge(tag::router())
+        .merge(music::router())
         .merge(proxy::router())
+        .merge(media::router())
+        .merge(redirect::router())
+        .merge(metrics::router())
 }
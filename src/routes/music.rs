@@ -0,0 +1,38 @@
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::{Html, IntoResponse},
+    routing::get,
+    Router,
+};
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::tiktok::link::is_music_slug;
+use crate::tiktok::types::MusicInfo;
+
+#[derive(Template)]
+#[template(path = "music.html")]
+struct MusicTemplate {
+    music: MusicInfo,
+}
+
+async fn get_music(
+    State(state): State<AppState>,
+    Path(slug): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    if !is_music_slug(&slug) {
+        return Err(AppError::InvalidUrl);
+    }
+    
+    tracing::info!("Fetching music: {}", slug);
+    
+    let music = state.source.fetch_music(&slug).await?;
+    
+    let template = MusicTemplate { music };
+    Ok(Html(template.render().map_err(|_| AppError::Internal)?))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/music/:slug", get(get_music))
+}
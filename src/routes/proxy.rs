@@ -1,38 +1,300 @@
 use axum::{
-    body::Body,
-    extract::Query,
-    http::{header, StatusCode},
-    response::{IntoResponse, Response},
+    body::{Body, Bytes},
+    extract::{Query, Request, State},
+    http::{header, response::Builder, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::Response,
     routing::get,
     Router,
 };
+use futures_util::{stream, StreamExt};
 use serde::Deserialize;
+use std::io;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+use url::Url;
 
 use crate::error::AppError;
-use crate::tiktok::client::get_http_client;
+use crate::image_transform::Transform;
+use crate::media_cache::{CachedMedia, MediaCache};
+use crate::media_type;
+use crate::signing;
+use crate::state::AppState;
+
+const CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// Images are read into memory whole to be resized, unlike streamed media
+const IMAGE_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Client headers passed upstream so seeking works
+const FORWARDED_REQUEST_HEADERS: [HeaderName; 2] = [header::RANGE, header::IF_RANGE];
+
+/// The only upstream headers relayed back, everything else is dropped
+const RELAYED_RESPONSE_HEADERS: [HeaderName; 3] = [
+    header::CONTENT_LENGTH,
+    header::CONTENT_RANGE,
+    header::ACCEPT_RANGES,
+];
+
+/// Outcome of serving a CDN URL
+pub(super) enum Served {
+    Response(Response),
+    /// The CDN refused the URL, usually because its signature expired.
+    /// The request is handed back so the caller can retry with a fresh URL.
+    Forbidden(Request),
+}
 
 #[derive(Deserialize)]
 pub struct ProxyQuery {
     url: String,
+    expires: Option<u64>,
+    sig: Option<String>,
+    /// Image resizing, see [`Transform`]
+    w: Option<u32>,
+    h: Option<u32>,
+    q: Option<u8>,
+    fmt: Option<String>,
 }
 
 /// Proxy media (video/images) through our server to prevent TikTok tracking
-async fn proxy_media(Query(params): Query<ProxyQuery>) -> Result<impl IntoResponse, AppError> {
-    let url = urlencoding::decode(&params.url)
-        .map_err(|_| AppError::InvalidUrl)?
-        .to_string();
+async fn proxy_media(
+    State(state): State<AppState>,
+    Query(params): Query<ProxyQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    // Only links from pages we rendered
+    signing::signer()
+        .verify(&params.url, params.expires, params.sig.as_deref())
+        .map_err(AppError::BadSignature)?;
+    
+    // Only allow https URLs on the CDN allowlist
+    let url = state.media_policy.parse(&params.url)?;
+    
+    if let Some(transform) = Transform::from_params(params.w, params.h, params.q, params.fmt.as_deref())? {
+        return serve_transformed(&state, &url, transform, request).await;
+    }
+    
+    match serve_media(&state, &url, request).await? {
+        Served::Response(response) => Ok(response),
+        Served::Forbidden(_) => Err(AppError::NotFound),
+    }
+}
+
+/// Serve an allowed CDN URL from the media cache or by relaying it from upstream
+pub(super) async fn serve_media(state: &AppState, url: &Url, request: Request) -> Result<Served, AppError> {
+    let cache = state.media_cache.as_ref()
+        .and_then(|cache| MediaCache::key_for(url.as_str()).map(|key| (cache, key)));
     
-    // Only allow TikTok CDN URLs
-    if !is_allowed_url(&url) {
-        return Err(AppError::InvalidUrl);
+    if let Some((cache, key)) = &cache {
+        if let Some(media) = cache.lookup(key) {
+            tracing::debug!("Serving cached media: {}", url);
+            return Ok(Served::Response(serve_cached(cache, &media, request).await));
+        }
     }
     
     tracing::debug!("Proxying media: {}", url);
     
-    let client = get_http_client();
-    let response = client
-        .get(&url)
-        .send()
+    let method = if request.method() == Method::HEAD { Method::HEAD } else { Method::GET };
+    let client_headers = request.headers();
+    
+    let response = state.pool
+        .media(method.clone(), url.as_str(), |mut upstream| {
+            for name in FORWARDED_REQUEST_HEADERS {
+                if let Some(value) = client_headers.get(&name) {
+                    upstream = upstream.header(name, value);
+                }
+            }
+            upstream
+        })
+        .await
+        .map_err(|e| AppError::FetchError(e.to_string()))?;
+    
+    let status = response.status();
+    if status == StatusCode::FORBIDDEN {
+        return Ok(Served::Forbidden(request));
+    }
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        let mut builder = Response::builder().status(status);
+        if let Some(range) = response.headers().get(header::CONTENT_RANGE) {
+            builder = builder.header(header::CONTENT_RANGE, range);
+        }
+        return Ok(Served::Response(builder.body(Body::empty()).unwrap()));
+    }
+    if !status.is_success() {
+        return Err(AppError::NotFound);
+    }
+    
+    let declared = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    
+    if !media_type::is_acceptable(&declared) {
+        tracing::warn!("Refusing {} from {}", declared, url);
+        return Err(AppError::UnsafeMedia("not an image, video or audio file"));
+    }
+    if total_size(&response).is_some_and(|size| size > state.media_max_bytes) {
+        return Err(AppError::UnsafeMedia("file is too large"));
+    }
+    
+    // Only complete bodies are stored; partial responses are relayed as they are
+    let complete = status == StatusCode::OK && method == Method::GET;
+    
+    if let Some((cache, key)) = cache.as_ref().filter(|_| complete) {
+        if cache.should_cache(&declared, response.content_length()) {
+            let body = response.bytes()
+                .await
+                .map_err(|e| AppError::FetchError(e.to_string()))?;
+            
+            let content_type = media_type::confirm(&declared, &body)
+                .ok_or(AppError::UnsafeMedia("content doesn't match its type"))?;
+            
+            return Ok(Served::Response(match cache.insert(key, content_type, &body).await {
+                Ok(media) => serve_cached(cache, &media, request).await,
+                Err(e) => {
+                    tracing::warn!("Failed to cache {}: {}", url, e);
+                    let builder = Response::builder()
+                        .header(header::CONTENT_LENGTH, body.len());
+                    media_headers(builder, content_type)
+                        .body(Body::from(body))
+                        .unwrap()
+                }
+            }));
+        }
+    }
+    
+    let mut builder = Response::builder().status(status);
+    for name in &RELAYED_RESPONSE_HEADERS {
+        if let Some(value) = response.headers().get(name) {
+            builder = builder.header(name, value);
+        }
+    }
+    
+    if method == Method::HEAD {
+        let content_type = media_type::essence(&declared);
+        return Ok(Served::Response(media_headers(builder, &content_type).body(Body::empty()).unwrap()));
+    }
+    
+    let starts_at_zero = status == StatusCode::OK || range_start(&response) == Some(0);
+    let max_bytes = state.media_max_bytes;
+    let mut stream = response.bytes_stream();
+    
+    // Look at the first bytes before committing to a response
+    let mut head = Vec::new();
+    while head.len() < media_type::SNIFF_LEN {
+        match stream.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk.map_err(|e| AppError::FetchError(e.to_string()))?),
+            None => break,
+        }
+    }
+    
+    let content_type = if starts_at_zero {
+        media_type::confirm(&declared, &head)
+            .ok_or(AppError::UnsafeMedia("content doesn't match its type"))?
+            .to_string()
+    } else if media_type::is_media_type(&declared) {
+        // Mid-file ranges have no magic bytes, trust a specific declared type
+        media_type::essence(&declared)
+    } else {
+        return Err(AppError::UnsafeMedia("content type can't be verified"));
+    };
+    
+    // Cut the stream off if upstream sends more than it announced or we allow
+    let mut sent = 0u64;
+    let stream = stream::once(async move { Ok(Bytes::from(head)) })
+        .chain(stream)
+        .map(move |chunk| {
+            let chunk = chunk.map_err(io::Error::other)?;
+            sent += chunk.len() as u64;
+            if sent > max_bytes {
+                return Err(io::Error::other("media exceeds the size limit"));
+            }
+            Ok(chunk)
+        });
+    
+    Ok(Served::Response(media_headers(builder, &content_type).body(Body::from_stream(stream)).unwrap()))
+}
+
+/// Serve a resized/re-encoded image, cached as its own media cache entry,
+/// or in memory when there is no media cache
+async fn serve_transformed(state: &AppState, url: &Url, transform: Transform, request: Request) -> Result<Response, AppError> {
+    let keys = MediaCache::key_for(url.as_str()).map(|key| {
+        let variant_key = MediaCache::variant_key(&key, &transform.variant());
+        (key, variant_key)
+    });
+    let cache = state.media_cache.as_ref().zip(keys.as_ref());
+    
+    if let Some((cache, (_, variant_key))) = cache {
+        if let Some(media) = cache.lookup(variant_key) {
+            return Ok(serve_cached(cache, &media, request).await);
+        }
+    } else if let Some((_, variant_key)) = &keys {
+        if let Some((body, content_type)) = state.resized.get(variant_key) {
+            return Ok(image_response(body, content_type));
+        }
+    }
+    
+    let original = cache.map(|(cache, (key, _))| (cache.as_ref(), key.as_str()));
+    let (source, source_type) = fetch_image(state, url, original).await?;
+    
+    let source_for_transform = source.clone();
+    let transformed = tokio::task::spawn_blocking(move || transform.apply(&source_for_transform))
+        .await
+        .map_err(|_| AppError::Internal)?;
+    
+    let body = match transformed {
+        Ok(body) => Bytes::from(body),
+        Err(e) if transform.explicit_format => {
+            tracing::debug!("Could not convert {}: {}", url, e);
+            return Err(AppError::Unconvertible(transform.format.content_type()));
+        }
+        Err(e) => {
+            // HEIC and AVIF have no pure Rust decoder, hand those out untouched
+            // and don't store them as if they were the resized variant
+            tracing::debug!("Serving {} unconverted: {}", url, e);
+            return Ok(image_response(source, source_type));
+        }
+    };
+    let content_type = transform.format.content_type();
+    
+    match (cache, &keys) {
+        (Some((cache, (_, variant_key))), _) => match cache.insert(variant_key, content_type, &body).await {
+            Ok(media) => return Ok(serve_cached(cache, &media, request).await),
+            Err(e) => tracing::warn!("Failed to cache resized {}: {}", url, e),
+        },
+        (None, Some((_, variant_key))) => state.resized.insert(variant_key.clone(), body.clone(), content_type),
+        (None, None) => {}
+    }
+    
+    Ok(image_response(body, content_type))
+}
+
+fn image_response(body: Bytes, content_type: &str) -> Response {
+    let builder = Response::builder().header(header::CONTENT_LENGTH, body.len());
+    media_headers(builder, content_type).body(Body::from(body)).unwrap()
+}
+
+/// Complete, verified image bytes from the media cache or upstream
+pub(super) async fn fetch_image(
+    state: &AppState,
+    url: &Url,
+    cache: Option<(&MediaCache, &str)>,
+) -> Result<(Bytes, &'static str), AppError> {
+    if let Some((cache, key)) = cache {
+        if let Some(media) = cache.lookup(key).filter(|media| media.size <= IMAGE_MAX_BYTES) {
+            if let Ok(body) = tokio::fs::read(cache.object_path(&media)).await {
+                if let Some(content_type) = media_type::confirm(&media.content_type, &body) {
+                    return only_images(Bytes::from(body), content_type);
+                }
+            }
+        }
+    }
+    
+    tracing::debug!("Fetching image to resize: {}", url);
+    
+    let response = state.pool
+        .media(Method::GET, url.as_str(), |upstream| upstream)
         .await
         .map_err(|e| AppError::FetchError(e.to_string()))?;
     
@@ -40,42 +302,124 @@ async fn proxy_media(Query(params): Query<ProxyQuery>) -> Result<impl IntoRespon
         return Err(AppError::NotFound);
     }
     
-    // Get content type
-    let content_type = response
+    let declared = response
         .headers()
         .get(header::CONTENT_TYPE)
         .and_then(|v| v.to_str().ok())
         .unwrap_or("application/octet-stream")
         .to_string();
     
-    // Stream the response body
-    let stream = response.bytes_stream();
-    let body = Body::from_stream(stream);
+    if !media_type::may_be_image(&declared) {
+        return Err(AppError::UnsafeMedia("only images can be resized"));
+    }
+    if response.content_length().is_some_and(|size| size > IMAGE_MAX_BYTES) {
+        return Err(AppError::UnsafeMedia("file is too large"));
+    }
+    
+    let mut stream = response.bytes_stream();
+    let mut body = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        body.extend_from_slice(&chunk.map_err(|e| AppError::FetchError(e.to_string()))?);
+        if body.len() as u64 > IMAGE_MAX_BYTES {
+            return Err(AppError::UnsafeMedia("file is too large"));
+        }
+    }
+    
+    let content_type = media_type::confirm(&declared, &body)
+        .ok_or(AppError::UnsafeMedia("content doesn't match its type"))?;
+    let (body, content_type) = only_images(Bytes::from(body), content_type)?;
+    
+    // Keep the original too, other sizes are made from it
+    if let Some((cache, key)) = cache {
+        if cache.should_cache(content_type, Some(body.len() as u64)) {
+            if let Err(e) = cache.insert(key, content_type, &body).await {
+                tracing::warn!("Failed to cache {}: {}", url, e);
+            }
+        }
+    }
     
-    Ok(Response::builder()
-        .status(StatusCode::OK)
+    Ok((body, content_type))
+}
+
+fn only_images(body: Bytes, content_type: &'static str) -> Result<(Bytes, &'static str), AppError> {
+    if content_type.starts_with("image/") {
+        Ok((body, content_type))
+    } else {
+        Err(AppError::UnsafeMedia("only images can be resized"))
+    }
+}
+
+/// Headers every proxied file gets, whatever upstream sent
+fn media_headers(builder: Builder, content_type: &str) -> Builder {
+    builder
         .header(header::CONTENT_TYPE, content_type)
-        .header(header::CACHE_CONTROL, "public, max-age=86400")
-        .body(body)
-        .unwrap())
+        .header(header::CACHE_CONTROL, CACHE_CONTROL)
+        .header(header::CONTENT_SECURITY_POLICY, "sandbox")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"media.{}\"", media_type::extension(content_type)),
+        )
+}
+
+/// Full size of the upstream object, from `Content-Range` for partial responses
+fn total_size(response: &reqwest::Response) -> Option<u64> {
+    match response.headers().get(header::CONTENT_RANGE) {
+        Some(range) => range.to_str().ok()?.rsplit('/').next()?.parse().ok(),
+        None => response.content_length(),
+    }
+}
+
+/// First byte offset of a `206` response
+fn range_start(response: &reqwest::Response) -> Option<u64> {
+    let range = response.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+    range.strip_prefix("bytes ")?.split('-').next()?.trim().parse().ok()
+}
+
+/// Serve a cached object, letting `ServeFile` handle Range and If-Modified-Since
+async fn serve_cached(cache: &MediaCache, media: &CachedMedia, request: Request) -> Response {
+    let etag = HeaderValue::from_str(&media.etag()).unwrap();
+    
+    if if_none_match(request.headers(), &etag) {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag)
+            .header(header::CACHE_CONTROL, CACHE_CONTROL)
+            .body(Body::empty())
+            .unwrap();
+    }
+    
+    let mime = media.content_type
+        .parse()
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    
+    let mut response = match ServeFile::new_with_mime(cache.object_path(media), &mime).oneshot(request).await {
+        Ok(response) => response.map(Body::new),
+        Err(never) => match never {},
+    };
+    
+    let disposition = format!("inline; filename=\"media.{}\"", media_type::extension(&media.content_type));
+    
+    let headers = response.headers_mut();
+    headers.insert(header::ETAG, etag);
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL));
+    headers.insert(header::CONTENT_SECURITY_POLICY, HeaderValue::from_static("sandbox"));
+    headers.insert(header::CONTENT_DISPOSITION, HeaderValue::from_str(&disposition).unwrap());
+    
+    response
 }
 
-fn is_allowed_url(url: &str) -> bool {
-    // Only allow TikTok CDN domains
-    let allowed_domains = [
-        "tiktokcdn.com",
-        "tiktokcdn-us.com",
-        "tiktokv.com",
-        "muscdn.com",
-        "byteoversea.com",
-        "ibytedtos.com",
-        "tiktokcdn-in.com",
-    ];
-    
-    allowed_domains.iter().any(|domain| url.contains(domain))
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag.as_bytes() == etag.as_bytes())
 }
 
-pub fn router() -> Router {
+pub fn router() -> Router<AppState> {
     Router::new()
+        // `get` also answers HEAD, the handler skips the body for it
         .route("/proxy", get(proxy_media))
 }
@@ -0,0 +1,76 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Redirect},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use url::Url;
+
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::tiktok::link::TikTokLink;
+
+/// Upper bound on remembered short codes before the map is reset
+const MAX_CACHED_CODES: usize = 10_000;
+
+#[derive(Deserialize)]
+pub struct RedirectQuery {
+    url: String,
+}
+
+/// Resolve a TikTok short link server-side and redirect to the local page
+async fn resolve_redirect(
+    State(state): State<AppState>,
+    Query(params): Query<RedirectQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    match TikTokLink::parse(&params.url) {
+        Some(TikTokLink::ShortLink(url)) => resolve(&state, url).await,
+        // Already canonical, no need to ask upstream
+        Some(link) => Ok(Redirect::to(&link.to_local_path())),
+        None => Err(AppError::InvalidUrl),
+    }
+}
+
+/// `www.tiktok.com/t/{code}` rewritten onto the instance by Redirector
+async fn resolve_share_path(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    match TikTokLink::parse(&format!("https://www.tiktok.com/t/{}", code)) {
+        Some(TikTokLink::ShortLink(url)) => resolve(&state, url).await,
+        _ => Err(AppError::InvalidUrl),
+    }
+}
+
+async fn resolve(state: &AppState, url: Url) -> Result<Redirect, AppError> {
+    let code = url.as_str().to_string();
+    
+    if let Some(path) = state.short_links.read().unwrap().get(&code) {
+        tracing::debug!("Short link {} served from cache", code);
+        return Ok(Redirect::to(path));
+    }
+    
+    tracing::info!("Resolving short link: {}", code);
+    
+    let target = state.source.resolve_short_link(&url).await?;
+    let path = match TikTokLink::parse(target.as_str()) {
+        // A short link that lands on another short link would loop forever
+        Some(TikTokLink::ShortLink(_)) | None => return Err(AppError::ParseError),
+        Some(link) => link.to_local_path(),
+    };
+    
+    let mut resolved = state.short_links.write().unwrap();
+    if resolved.len() >= MAX_CACHED_CODES {
+        resolved.clear();
+    }
+    resolved.insert(code, path.clone());
+    
+    Ok(Redirect::to(&path))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/redirect", get(resolve_redirect))
+        .route("/t/:code", get(resolve_share_path))
+}
@@ -1,32 +1,52 @@
 use askama::Template;
 use axum::{
-    extract::Path,
-    response::{Html, IntoResponse},
+    extract::{Path, Query, State},
+    response::{Html, IntoResponse, Redirect},
     routing::get,
     Router,
 };
 use crate::error::AppError;
-use crate::tiktok::{self, types::TagInfo};
+use crate::routes::PageQuery;
+use crate::state::AppState;
+use crate::tiktok::link::{self, TikTokLink};
+use crate::tiktok::types::TagInfo;
 
 #[derive(Template)]
 #[template(path = "tag.html")]
 struct TagTemplate {
     tag: TagInfo,
+    /// Whether this is a later page rather than the first one
+    paged: bool,
 }
 
-async fn get_tag(Path(tag_name): Path<String>) -> Result<impl IntoResponse, AppError> {
+async fn get_tag(
+    State(state): State<AppState>,
+    Path(tag_name): Path<String>,
+    Query(params): Query<PageQuery>,
+) -> Result<impl IntoResponse, AppError> {
     // Remove # if present
     let tag_name = tag_name.trim_start_matches('#');
     
+    render_tag(&state, tag_name, params.cursor()?).await
+}
+
+/// `/discover/{topic}` keyword feeds have no page of their own here, show the matching hashtag
+async fn get_discover(Path(topic): Path<String>) -> Result<Redirect, AppError> {
+    let tag = link::discover_tag(&topic).ok_or(AppError::InvalidUrl)?;
+    Ok(Redirect::to(&TikTokLink::Tag(tag).to_local_path()))
+}
+
+async fn render_tag(state: &AppState, tag_name: &str, cursor: Option<&str>) -> Result<Html<String>, AppError> {
     tracing::info!("Fetching tag: {}", tag_name);
     
-    let tag = tiktok::client::fetch_tag(tag_name).await?;
+    let tag = state.source.fetch_tag(tag_name, cursor).await?;
     
-    let template = TagTemplate { tag };
+    let template = TagTemplate { tag, paged: cursor.is_some() };
     Ok(Html(template.render().map_err(|_| AppError::Internal)?))
 }
 
-pub fn router() -> Router {
+pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/tag/{tag_name}", get(get_tag))
+        .route("/tag/:tag_name", get(get_tag))
+        .route("/discover/:topic", get(get_discover))
 }
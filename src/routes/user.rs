@@ -1,32 +1,43 @@
 use askama::Template;
 use axum::{
-    extract::Path,
+    extract::{Path, Query, State},
     response::{Html, IntoResponse},
     routing::get,
     Router,
 };
 use crate::error::AppError;
-use crate::tiktok::{self, types::UserInfo};
+use crate::routes::{validate_username, PageQuery};
+use crate::state::AppState;
+use crate::tiktok::types::UserInfo;
 
 #[derive(Template)]
 #[template(path = "user.html")]
 struct UserTemplate {
     user: UserInfo,
+    /// Whether this is an older page rather than the newest videos
+    paged: bool,
 }
 
-async fn get_user(Path(username): Path<String>) -> Result<impl IntoResponse, AppError> {
+async fn get_user(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Query(params): Query<PageQuery>,
+) -> Result<impl IntoResponse, AppError> {
     // Remove @ if present
     let username = username.trim_start_matches('@');
+    validate_username(username)?;
+    
+    let cursor = params.cursor()?;
     
     tracing::info!("Fetching user: {}", username);
     
-    let user = tiktok::client::fetch_user(username).await?;
+    let user = state.source.fetch_user(username, cursor).await?;
     
-    let template = UserTemplate { user };
+    let template = UserTemplate { user, paged: cursor.is_some() };
     Ok(Html(template.render().map_err(|_| AppError::Internal)?))
 }
 
-pub fn router() -> Router {
+pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/@{username}", get(get_user))
+        .route("/@:username", get(get_user))
 }
@@ -1,12 +1,13 @@
 use askama::Template;
 use axum::{
-    extract::Path,
+    extract::{Path, State},
     response::{Html, IntoResponse},
     routing::get,
     Router,
 };
 use crate::error::AppError;
-use crate::tiktok::{self, types::VideoInfo};
+use crate::state::AppState;
+use crate::tiktok::types::VideoInfo;
 
 #[derive(Template)]
 #[template(path = "video.html")]
@@ -14,16 +15,49 @@ struct VideoTemplate {
     video: VideoInfo,
 }
 
-async fn get_video(Path(video_id): Path<String>) -> Result<impl IntoResponse, AppError> {
+async fn get_video(
+    State(state): State<AppState>,
+    Path(video_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    render_video(&state, &video_id).await
+}
+
+/// `/@{username}/video/{id}` and `/@{username}/photo/{id}` permalinks
+async fn get_user_video(
+    State(state): State<AppState>,
+    Path((_username, video_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    render_video(&state, &video_id).await
+}
+
+/// `m.tiktok.com/v/{id}.html` mobile share links
+async fn get_mobile_video(
+    State(state): State<AppState>,
+    Path(file): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let video_id = file.strip_suffix(".html").unwrap_or(&file);
+    render_video(&state, video_id).await
+}
+
+async fn render_video(state: &AppState, video_id: &str) -> Result<Html<String>, AppError> {
+    if video_id.is_empty() || !video_id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(AppError::InvalidUrl);
+    }
+    
     tracing::info!("Fetching video: {}", video_id);
     
-    let video = tiktok::client::fetch_video(&video_id).await?;
+    let video = state.source.fetch_video(video_id).await?;
     
     let template = VideoTemplate { video };
     Ok(Html(template.render().map_err(|_| AppError::Internal)?))
 }
 
-pub fn router() -> Router {
+pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/video/{video_id}", get(get_video))
+        .route("/video/:video_id", get(get_video))
+        .route("/@:username/video/:video_id", get(get_user_video))
+        .route("/@:username/photo/:video_id", get(get_user_video))
+        .route("/embed/:video_id", get(get_video))
+        .route("/embed/v2/:video_id", get(get_video))
+        .route("/v/:file", get(get_mobile_video))
 }
@@ -0,0 +1,304 @@
+use hmac::{Hmac, Mac};
+use once_cell::sync::OnceCell;
+use sha2::Sha256;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Expiry times are rounded up to this, so a page rendered twice links the same
+/// URLs and browsers and the media cache can reuse them
+const EXPIRY_BUCKET_SECS: u64 = 600;
+
+static SIGNER: OnceCell<UrlSigner> = OnceCell::new();
+
+/// Why a `/proxy` link was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    Missing,
+    Invalid,
+    Expired,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SignatureError::Missing => "not signed",
+            SignatureError::Invalid => "invalid or has been tampered with",
+            SignatureError::Expired => "expired",
+        })
+    }
+}
+
+/// Signs `/proxy` and `/media` links with the instance secret so only pages we render can use them
+pub struct UrlSigner {
+    secret: Vec<u8>,
+    /// Secret before the last rotation and until when its links are still honoured
+    previous: Option<(Vec<u8>, SystemTime)>,
+    ttl: Duration,
+}
+
+impl UrlSigner {
+    /// `secret` falls back to a random one, which invalidates links on every restart
+    pub fn new(secret: Option<&str>, previous: Option<&str>, grace: Duration, ttl: Duration) -> Self {
+        let secret = match secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                tracing::warn!("PROXY_SECRET is not set, media links will stop working after a restart");
+                rand::random::<[u8; 32]>().to_vec()
+            }
+        };
+        
+        Self {
+            secret,
+            previous: previous.map(|previous| (previous.as_bytes().to_vec(), SystemTime::now() + grace)),
+            ttl,
+        }
+    }
+    
+    /// Signed `/proxy` path for a CDN URL
+    pub fn proxy_url(&self, url: &str) -> String {
+        let expires = unix_now() + self.ttl.as_secs();
+        let expires = expires.div_ceil(EXPIRY_BUCKET_SECS) * EXPIRY_BUCKET_SECS;
+        let signature = hex(&mac(&self.secret, url, expires).finalize().into_bytes());
+        
+        format!("/proxy?url={}&expires={}&sig={}", urlencoding::encode(url), expires, signature)
+    }
+    
+    pub fn verify(&self, url: &str, expires: Option<u64>, signature: Option<&str>) -> Result<(), SignatureError> {
+        let (Some(expires), Some(signature)) = (expires, signature) else {
+            return Err(SignatureError::Missing);
+        };
+        let signature = decode_hex(signature).ok_or(SignatureError::Invalid)?;
+        
+        let valid = self
+            .secrets()
+            .iter()
+            .any(|secret| mac(secret, url, expires).verify_slice(&signature).is_ok());
+        
+        // Only report expiry for links we actually issued
+        match (valid, expires < unix_now()) {
+            (false, _) => Err(SignatureError::Invalid),
+            (true, true) => Err(SignatureError::Expired),
+            (true, false) => Ok(()),
+        }
+    }
+    
+    /// Signature for a `/media` link. These don't expire, so a page links the
+    /// same URLs for as long as the secret stays the same.
+    pub fn media_signature(&self, kind: &str, id: &str) -> String {
+        hex(&media_mac(&self.secret, kind, id).finalize().into_bytes())
+    }
+    
+    pub fn verify_media(&self, kind: &str, id: &str, signature: Option<&str>) -> Result<(), SignatureError> {
+        let signature = decode_hex(signature.ok_or(SignatureError::Missing)?).ok_or(SignatureError::Invalid)?;
+        
+        self.secrets()
+            .iter()
+            .any(|secret| media_mac(secret, kind, id).verify_slice(&signature).is_ok())
+            .then_some(())
+            .ok_or(SignatureError::Invalid)
+    }
+    
+    /// The current secret, and the previous one during its grace period
+    fn secrets(&self) -> Vec<&[u8]> {
+        let mut secrets = vec![self.secret.as_slice()];
+        if let Some((previous, until)) = &self.previous {
+            if SystemTime::now() < *until {
+                secrets.push(previous);
+            }
+        }
+        secrets
+    }
+}
+
+/// Install the signer used by the `proxied_*_url` helpers, once at startup
+pub fn init(signer: UrlSigner) {
+    if SIGNER.set(signer).is_err() {
+        tracing::warn!("URL signer was already initialised");
+    }
+}
+
+pub fn signer() -> &'static UrlSigner {
+    SIGNER.get().expect("URL signer is initialised at startup")
+}
+
+/// Signed `/proxy` path for a CDN URL
+pub fn proxy_url(url: &str) -> String {
+    signer().proxy_url(url)
+}
+
+/// Signature for a `/media` link to `kind` of post or user `id`
+pub fn media_signature(kind: &str, id: &str) -> String {
+    signer().media_signature(kind, id)
+}
+
+fn mac(secret: &[u8], url: &str, expires: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(expires.to_string().as_bytes());
+    mac.update(b"\n");
+    mac.update(url.as_bytes());
+    mac
+}
+
+/// Starts with a word where `/proxy` MACs start with a number, so one can't pass for the other
+fn media_mac(secret: &[u8], kind: &str, id: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(b"media\n");
+    mac.update(kind.as_bytes());
+    mac.update(b"\n");
+    mac.update(id.as_bytes());
+    mac
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `None` for odd lengths too, the last pair comes up short
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const URL: &str = "https://p16-sign.tiktokcdn.com/obj/a.jpg?x-expires=1";
+    const HOUR: Duration = Duration::from_secs(3600);
+    
+    fn signer(secret: &str, previous: Option<&str>, grace: Duration) -> UrlSigner {
+        UrlSigner::new(Some(secret), previous, grace, HOUR)
+    }
+    
+    /// `url`, `expires` and `sig` of an issued `/proxy` link
+    fn issued(signer: &UrlSigner, url: &str) -> (String, u64, String) {
+        let link = url::Url::parse(&format!("http://localhost{}", signer.proxy_url(url))).unwrap();
+        let param = |name: &str| link.query_pairs().find(|(key, _)| key == name).unwrap().1.into_owned();
+        (param("url"), param("expires").parse().unwrap(), param("sig"))
+    }
+    
+    /// A link whose expiry has already passed, as `secret` would have signed it
+    fn expired_signature(secret: &str, url: &str) -> (u64, String) {
+        let expires = unix_now() - 60;
+        (expires, hex(&mac(secret.as_bytes(), url, expires).finalize().into_bytes()))
+    }
+    
+    #[test]
+    fn issued_links_verify() {
+        let signer = signer("current", None, HOUR);
+        let (url, expires, sig) = issued(&signer, URL);
+        
+        assert_eq!(url, URL);
+        assert!(expires > unix_now());
+        assert_eq!(expires % EXPIRY_BUCKET_SECS, 0);
+        assert_eq!(signer.verify(&url, Some(expires), Some(&sig)), Ok(()));
+    }
+    
+    #[test]
+    fn tampered_params_are_invalid() {
+        let signer = signer("current", None, HOUR);
+        let (url, expires, sig) = issued(&signer, URL);
+        
+        let other_url = URL.replace("a.jpg", "b.jpg");
+        assert_eq!(signer.verify(&other_url, Some(expires), Some(&sig)), Err(SignatureError::Invalid));
+        assert_eq!(signer.verify(&url, Some(expires + 1), Some(&sig)), Err(SignatureError::Invalid));
+        
+        let mut flipped = sig.clone().into_bytes();
+        flipped[0] = if flipped[0] == b'0' { b'1' } else { b'0' };
+        let flipped = String::from_utf8(flipped).unwrap();
+        assert_eq!(signer.verify(&url, Some(expires), Some(&flipped)), Err(SignatureError::Invalid));
+        
+        assert_eq!(signer.verify(&url, Some(expires), Some(&sig[1..])), Err(SignatureError::Invalid));
+        assert_eq!(signer.verify(&url, Some(expires), Some("not hex")), Err(SignatureError::Invalid));
+    }
+    
+    #[test]
+    fn missing_params() {
+        let signer = signer("current", None, HOUR);
+        let (url, expires, sig) = issued(&signer, URL);
+        
+        assert_eq!(signer.verify(&url, None, Some(&sig)), Err(SignatureError::Missing));
+        assert_eq!(signer.verify(&url, Some(expires), None), Err(SignatureError::Missing));
+    }
+    
+    #[test]
+    fn other_keys_are_invalid() {
+        let (url, expires, sig) = issued(&signer("someone else", None, HOUR), URL);
+        assert_eq!(
+            signer("current", None, HOUR).verify(&url, Some(expires), Some(&sig)),
+            Err(SignatureError::Invalid)
+        );
+    }
+    
+    #[test]
+    fn expired_links() {
+        let signer = signer("current", None, HOUR);
+        
+        let (expires, sig) = expired_signature("current", URL);
+        assert_eq!(signer.verify(URL, Some(expires), Some(&sig)), Err(SignatureError::Expired));
+        
+        // Only links we issued are reported as expired
+        let (expires, sig) = expired_signature("someone else", URL);
+        assert_eq!(signer.verify(URL, Some(expires), Some(&sig)), Err(SignatureError::Invalid));
+    }
+    
+    #[test]
+    fn rotated_key_is_honoured_during_the_grace_period() {
+        let old = signer("old", None, HOUR);
+        let (url, expires, sig) = issued(&old, URL);
+        let (expired_at, expired_sig) = expired_signature("old", URL);
+        
+        let rotated = signer("current", Some("old"), HOUR);
+        assert_eq!(rotated.verify(&url, Some(expires), Some(&sig)), Ok(()));
+        assert_eq!(rotated.verify(URL, Some(expired_at), Some(&expired_sig)), Err(SignatureError::Expired));
+        
+        // New links use the new key only
+        let (url, expires, sig) = issued(&rotated, URL);
+        assert_eq!(old.verify(&url, Some(expires), Some(&sig)), Err(SignatureError::Invalid));
+    }
+    
+    #[test]
+    fn rotated_key_is_refused_after_the_grace_period() {
+        let (url, expires, sig) = issued(&signer("old", None, HOUR), URL);
+        let (expired_at, expired_sig) = expired_signature("old", URL);
+        
+        let rotated = signer("current", Some("old"), Duration::ZERO);
+        assert_eq!(rotated.verify(&url, Some(expires), Some(&sig)), Err(SignatureError::Invalid));
+        assert_eq!(rotated.verify(URL, Some(expired_at), Some(&expired_sig)), Err(SignatureError::Invalid));
+    }
+    
+    #[test]
+    fn media_signatures() {
+        let signer = signer("current", None, HOUR);
+        let sig = signer.media_signature("video", "123");
+        
+        assert_eq!(sig, signer.media_signature("video", "123"));
+        assert_eq!(signer.verify_media("video", "123", Some(&sig)), Ok(()));
+        assert_eq!(signer.verify_media("cover", "123", Some(&sig)), Err(SignatureError::Invalid));
+        assert_eq!(signer.verify_media("video", "1234", Some(&sig)), Err(SignatureError::Invalid));
+        assert_eq!(signer.verify_media("video", "123", None), Err(SignatureError::Missing));
+        assert_eq!(signer.verify_media("video", "123", Some("zz")), Err(SignatureError::Invalid));
+    }
+    
+    #[test]
+    fn media_signatures_follow_key_rotation() {
+        let sig = signer("old", None, HOUR).media_signature("avatar", "someone");
+        
+        let rotated = signer("current", Some("old"), HOUR);
+        assert_eq!(rotated.verify_media("avatar", "someone", Some(&sig)), Ok(()));
+        
+        let rotated = signer("current", Some("old"), Duration::ZERO);
+        assert_eq!(rotated.verify_media("avatar", "someone", Some(&sig)), Err(SignatureError::Invalid));
+    }
+}
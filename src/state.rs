@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::image_transform::VariantCache;
+use crate::media_cache::MediaCache;
+use crate::signing::{self, UrlSigner};
+use crate::tiktok::cache::{CacheConfig, CachePolicy, CachingSource};
+use crate::tiktok::client::WebSource;
+use crate::tiktok::coalesce::CoalescingSource;
+use crate::tiktok::media_policy::MediaUrlPolicy;
+use crate::tiktok::proxy_pool::ProxyPool;
+use crate::tiktok::retry::{CircuitBreaker, RetryPolicy};
+use crate::tiktok::session::SessionManager;
+use crate::tiktok::source::TikTokSource;
+
+/// Shared state handed to every route handler
+#[derive(Clone)]
+pub struct AppState {
+    pub source: Arc<dyn TikTokSource>,
+    /// Outbound proxies shared by page fetches and media streaming
+    pub pool: Arc<ProxyPool>,
+    /// Disk cache for proxied media, when `MEDIA_CACHE_DIR` is set
+    pub media_cache: Option<Arc<MediaCache>>,
+    /// Resized images in memory, used when there is no `media_cache`
+    pub resized: Arc<VariantCache>,
+    /// Short link -> local path, so repeated shares skip the upstream round trip
+    pub short_links: Arc<RwLock<HashMap<String, String>>>,
+    /// Which URLs `/proxy` may fetch
+    pub media_policy: Arc<MediaUrlPolicy>,
+    /// Size cap for a single proxied file
+    pub media_max_bytes: u64,
+}
+
+impl AppState {
+    pub fn from_config(config: &Config) -> Self {
+        signing::init(UrlSigner::new(
+            config.proxy_secret.as_deref(),
+            config.proxy_previous_secret.as_deref(),
+            Duration::from_secs(config.proxy_secret_grace_secs),
+            Duration::from_secs(config.proxy_url_ttl_secs),
+        ));
+        
+        let retry = RetryPolicy {
+            max_retries: config.retry_max,
+            base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            ..RetryPolicy::default()
+        };
+        let breaker = CircuitBreaker::new(
+            config.breaker_threshold,
+            Duration::from_secs(config.breaker_cooldown_secs),
+        );
+        
+        let media_policy = Arc::new(MediaUrlPolicy::new(&config.media_allowed_hosts));
+        
+        let pool = ProxyPool::new(
+            &config.upstream_proxies,
+            config.proxy_rotation,
+            &config.proxy_region_routes,
+            config.proxy_max_failures,
+            Duration::from_secs(config.proxy_block_cooldown_secs),
+            Arc::clone(&media_policy),
+        )
+        .expect("UPSTREAM_PROXIES is invalid");
+        let pool = Arc::new(pool);
+        
+        if !pool.is_empty() {
+            tracing::info!("Routing upstream traffic through {} proxies", config.upstream_proxies.len());
+            pool.spawn_health_checks(
+                Duration::from_secs(config.proxy_health_check_secs),
+                config.proxy_health_check_url.clone(),
+            );
+        }
+        
+        let sessions = Arc::new(SessionManager::new(
+            config.session_pool_size,
+            &config.tiktok_base_url,
+            Arc::clone(&pool),
+            Duration::from_secs(config.session_max_age_secs),
+        ));
+        
+        let source = WebSource::new(&config.tiktok_base_url, Arc::clone(&pool), sessions, retry, breaker);
+        let source = CoalescingSource::new(Arc::new(source));
+        
+        let policy = |ttl_secs| CachePolicy {
+            ttl: Duration::from_secs(ttl_secs),
+            stale: Duration::from_secs(config.cache_stale_secs),
+            not_found_ttl: Duration::from_secs(config.cache_not_found_ttl_secs),
+            max_entries: config.cache_max_entries,
+        };
+        let cache = CacheConfig {
+            user: policy(config.cache_user_ttl_secs),
+            video: policy(config.cache_video_ttl_secs),
+            tag: policy(config.cache_tag_ttl_secs),
+        };
+        
+        let media_cache = config.media_cache_dir.as_ref().map(|dir| {
+            let cache = MediaCache::open(
+                Path::new(dir),
+                config.media_cache_max_bytes,
+                config.media_cache_max_object_bytes,
+                config.media_cache_videos,
+            )
+            .expect("MEDIA_CACHE_DIR is not usable");
+            Arc::new(cache)
+        });
+        
+        Self {
+            source: Arc::new(CachingSource::new(Arc::new(source), cache)),
+            pool,
+            media_cache,
+            resized: Arc::new(VariantCache::default()),
+            short_links: Arc::default(),
+            media_policy,
+            media_max_bytes: config.media_max_bytes,
+        }
+    }
+}
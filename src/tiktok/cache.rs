@@ -0,0 +1,317 @@
+use async_trait::async_trait;
+use lru::LruCache;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::error::AppError;
+use super::coalesce::FetchKey;
+use super::source::TikTokSource;
+use super::types::{UserInfo, VideoInfo, TagInfo, MusicInfo};
+
+/// Signed CDN URLs stop working at `x-expires`, so entries must be gone a little before that
+const CDN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Lifetimes for one kind of cached object
+#[derive(Debug, Clone, Copy)]
+pub struct CachePolicy {
+    /// How long an entry is served without refreshing
+    pub ttl: Duration,
+    /// How long after `ttl` an entry is still served while it refreshes in the background
+    pub stale: Duration,
+    /// How long a `NotFound` is remembered
+    pub not_found_ttl: Duration,
+    pub max_entries: usize,
+}
+
+/// Parsed objects that can be cached
+pub trait Cacheable {
+    /// Signed CDN URLs inside the object, which bound how long it stays usable
+    fn cdn_urls(&self) -> Vec<&str>;
+    
+    /// Some fields couldn't be extracted, keep it only as long as a `NotFound`
+    /// so the next request gets another go at the full data
+    fn is_partial(&self) -> bool {
+        false
+    }
+}
+
+impl Cacheable for VideoInfo {
+    fn cdn_urls(&self) -> Vec<&str> {
+        let mut urls = vec![self.video_url.as_str(), &self.thumbnail_url, &self.author_avatar];
+        urls.extend(self.variants.iter().map(|variant| variant.url.as_str()));
+        urls.extend(self.images.iter().map(|image| image.url.as_str()));
+        urls.push(&self.audio_url);
+        urls
+    }
+    
+    fn is_partial(&self) -> bool {
+        !self.missing.is_empty()
+    }
+}
+
+impl Cacheable for UserInfo {
+    fn cdn_urls(&self) -> Vec<&str> {
+        let mut urls = vec![self.avatar_url.as_str()];
+        urls.extend(self.videos.iter().flat_map(VideoInfo::cdn_urls));
+        urls
+    }
+    
+    fn is_partial(&self) -> bool {
+        !self.missing.is_empty()
+    }
+}
+
+impl Cacheable for TagInfo {
+    fn cdn_urls(&self) -> Vec<&str> {
+        self.videos.iter().flat_map(VideoInfo::cdn_urls).collect()
+    }
+    
+    fn is_partial(&self) -> bool {
+        !self.missing.is_empty()
+    }
+}
+
+/// Earliest `x-expires` (unix seconds) among the given URLs
+pub fn cdn_expiry<'a>(urls: impl IntoIterator<Item = &'a str>) -> Option<SystemTime> {
+    urls.into_iter()
+        .filter(|url| !url.is_empty())
+        .filter_map(|url| Url::parse(url).ok())
+        .filter_map(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "x-expires" || key == "expire")
+                .and_then(|(_, value)| value.parse::<u64>().ok())
+        })
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+        .min()
+}
+
+struct Entry<T> {
+    /// `None` records a `NotFound`
+    value: Option<T>,
+    fresh_until: Instant,
+    stale_until: Instant,
+    refreshing: bool,
+}
+
+enum Lookup<T> {
+    Fresh(Option<T>),
+    Stale(Option<T>),
+    Miss,
+}
+
+/// Bounded LRU of parsed objects with per-entry expiry
+pub struct TtlCache<T> {
+    entries: Arc<Mutex<LruCache<FetchKey, Entry<T>>>>,
+    policy: CachePolicy,
+}
+
+impl<T> Clone for TtlCache<T> {
+    fn clone(&self) -> Self {
+        Self { entries: Arc::clone(&self.entries), policy: self.policy }
+    }
+}
+
+impl<T: Cacheable + Clone + Send + 'static> TtlCache<T> {
+    pub fn new(policy: CachePolicy) -> Self {
+        let capacity = NonZeroUsize::new(policy.max_entries).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: Arc::new(Mutex::new(LruCache::new(capacity))),
+            policy,
+        }
+    }
+    
+    fn is_enabled(&self) -> bool {
+        self.policy.max_entries > 0
+    }
+    
+    fn lookup(&self, key: &FetchKey) -> Lookup<T> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        
+        match entries.get_mut(key) {
+            Some(entry) if now < entry.fresh_until => Lookup::Fresh(entry.value.clone()),
+            Some(entry) if now < entry.stale_until && !entry.refreshing => {
+                entry.refreshing = true;
+                Lookup::Stale(entry.value.clone())
+            }
+            // Someone is already refreshing it, keep serving stale
+            Some(entry) if now < entry.stale_until => Lookup::Fresh(entry.value.clone()),
+            Some(_) => {
+                entries.pop(key);
+                Lookup::Miss
+            }
+            None => Lookup::Miss,
+        }
+    }
+    
+    fn store(&self, key: FetchKey, result: &Result<T, AppError>) {
+        let now = Instant::now();
+        
+        let entry = match result {
+            Ok(value) => {
+                let (mut ttl, mut stale) = if value.is_partial() {
+                    (self.policy.not_found_ttl, Duration::ZERO)
+                } else {
+                    (self.policy.ttl, self.policy.stale)
+                };
+                
+                // Don't outlive the media URLs inside the entry
+                if let Some(expiry) = cdn_expiry(value.cdn_urls()) {
+                    let remaining = expiry
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default()
+                        .saturating_sub(CDN_EXPIRY_MARGIN);
+                    ttl = ttl.min(remaining);
+                    stale = stale.min(remaining - ttl);
+                }
+                if ttl.is_zero() {
+                    return;
+                }
+                
+                Entry {
+                    value: Some(value.clone()),
+                    fresh_until: now + ttl,
+                    stale_until: now + ttl + stale,
+                    refreshing: false,
+                }
+            }
+            Err(AppError::NotFound) => Entry {
+                value: None,
+                fresh_until: now + self.policy.not_found_ttl,
+                stale_until: now + self.policy.not_found_ttl,
+                refreshing: false,
+            },
+            // Transient failures aren't cached
+            Err(_) => return,
+        };
+        
+        self.entries.lock().unwrap().put(key, entry);
+    }
+    
+    pub fn invalidate(&self, key: &FetchKey) {
+        self.entries.lock().unwrap().pop(key);
+    }
+    
+    /// Let the next stale hit try refreshing again
+    fn refresh_failed(&self, key: &FetchKey) {
+        if let Some(entry) = self.entries.lock().unwrap().peek_mut(key) {
+            entry.refreshing = false;
+        }
+    }
+    
+    /// Serve `key` from the cache, calling `fetch` on a miss and in the background once stale
+    pub async fn get_or_fetch<F, Fut>(&self, key: FetchKey, fetch: F) -> Result<T, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>> + Send + 'static,
+    {
+        if !self.is_enabled() {
+            return fetch().await;
+        }
+        
+        match self.lookup(&key) {
+            Lookup::Fresh(value) => value.ok_or(AppError::NotFound),
+            Lookup::Stale(value) => {
+                tracing::debug!("Serving stale {:?}, refreshing", key);
+                let cache = self.clone();
+                let future = fetch();
+                tokio::spawn(async move {
+                    let result = future.await;
+                    if let Err(e) = &result {
+                        tracing::warn!("Background refresh of {:?} failed: {}", key, e);
+                        cache.refresh_failed(&key);
+                    }
+                    cache.store(key, &result);
+                });
+                value.ok_or(AppError::NotFound)
+            }
+            Lookup::Miss => {
+                let result = fetch().await;
+                self.store(key, &result);
+                result
+            }
+        }
+    }
+}
+
+/// Cache sizes and lifetimes for each kind of page
+#[derive(Debug, Clone, Copy)]
+pub struct CacheConfig {
+    pub user: CachePolicy,
+    pub video: CachePolicy,
+    pub tag: CachePolicy,
+}
+
+/// Wraps a source with an in-memory cache of parsed users, videos and tags
+pub struct CachingSource {
+    inner: Arc<dyn TikTokSource>,
+    users: TtlCache<UserInfo>,
+    videos: TtlCache<VideoInfo>,
+    tags: TtlCache<TagInfo>,
+}
+
+impl CachingSource {
+    pub fn new(inner: Arc<dyn TikTokSource>, config: CacheConfig) -> Self {
+        Self {
+            inner,
+            users: TtlCache::new(config.user),
+            videos: TtlCache::new(config.video),
+            tags: TtlCache::new(config.tag),
+        }
+    }
+}
+
+#[async_trait]
+impl TikTokSource for CachingSource {
+    async fn fetch_user(&self, username: &str, cursor: Option<&str>) -> Result<UserInfo, AppError> {
+        let key = FetchKey::new("user", username, cursor);
+        let (inner, username, cursor) = (Arc::clone(&self.inner), username.to_string(), cursor.map(String::from));
+        self.users
+            .get_or_fetch(key, move || async move { inner.fetch_user(&username, cursor.as_deref()).await })
+            .await
+    }
+    
+    async fn fetch_profile(&self, username: &str) -> Result<UserInfo, AppError> {
+        let key = FetchKey::new("profile", username, None);
+        let (inner, username) = (Arc::clone(&self.inner), username.to_string());
+        self.users
+            .get_or_fetch(key, move || async move { inner.fetch_profile(&username).await })
+            .await
+    }
+    
+    async fn fetch_video(&self, video_id: &str) -> Result<VideoInfo, AppError> {
+        let key = FetchKey::new("video", video_id, None);
+        let (inner, video_id) = (Arc::clone(&self.inner), video_id.to_string());
+        self.videos
+            .get_or_fetch(key, move || async move { inner.fetch_video(&video_id).await })
+            .await
+    }
+    
+    async fn fetch_tag(&self, tag_name: &str, cursor: Option<&str>) -> Result<TagInfo, AppError> {
+        let key = FetchKey::new("tag", tag_name, cursor);
+        let (inner, tag_name, cursor) = (Arc::clone(&self.inner), tag_name.to_string(), cursor.map(String::from));
+        self.tags
+            .get_or_fetch(key, move || async move { inner.fetch_tag(&tag_name, cursor.as_deref()).await })
+            .await
+    }
+    
+    async fn fetch_music(&self, slug: &str) -> Result<MusicInfo, AppError> {
+        self.inner.fetch_music(slug).await
+    }
+    
+    async fn resolve_short_link(&self, url: &Url) -> Result<Url, AppError> {
+        self.inner.resolve_short_link(url).await
+    }
+    
+    fn invalidate_video(&self, video_id: &str) {
+        self.videos.invalidate(&FetchKey::new("video", video_id, None));
+    }
+    
+    fn invalidate_user(&self, username: &str) {
+        self.users.invalidate(&FetchKey::new("user", username, None));
+        self.users.invalidate(&FetchKey::new("profile", username, None));
+    }
+}
@@ -0,0 +1,162 @@
+use reqwest::StatusCode;
+use std::fmt;
+use url::Url;
+
+/// Interstitials TikTok serves instead of content when it distrusts a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeKind {
+    /// Slider/rotate captcha or "verify you're human" page
+    Captcha,
+    /// Web application firewall block or JS challenge
+    Waf,
+    /// Content hidden behind a login prompt
+    LoginWall,
+}
+
+impl ChallengeKind {
+    pub fn metric_label(&self) -> &'static str {
+        match self {
+            ChallengeKind::Captcha => "captcha",
+            ChallengeKind::Waf => "waf",
+            ChallengeKind::LoginWall => "login_wall",
+        }
+    }
+}
+
+impl fmt::Display for ChallengeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChallengeKind::Captcha => write!(f, "captcha"),
+            ChallengeKind::Waf => write!(f, "firewall block"),
+            ChallengeKind::LoginWall => write!(f, "login wall"),
+        }
+    }
+}
+
+/// What a successful response to the request looks like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    /// An HTML page carrying one of the page data scripts
+    Page,
+    /// A JSON API body
+    Json,
+}
+
+/// Scripts holding the data of a real page. Challenge documents carry none of them.
+const PAGE_DATA_SCRIPTS: &[&str] = &[
+    "id=\"__UNIVERSAL_DATA_FOR_REHYDRATION__\"",
+    "id=\"SIGI_STATE\"",
+    "id=\"__NEXT_DATA__\"",
+    "id=\"__FRONTITY_CONNECT_STATE__\"",
+    "type=\"application/ld+json\"",
+];
+
+const CAPTCHA_MARKERS: &[&str] = &[
+    "captcha_container",
+    "captcha-verify",
+    "secsdk-captcha",
+    "verify-bar-close",
+    "\"verifyConfig\"",
+];
+
+const WAF_MARKERS: &[&str] = &[
+    "_wafchallengeid",
+    "waf-jschallenge",
+    "/_waf/",
+    "Access Denied",
+];
+
+const LOGIN_MARKERS: &[&str] = &[
+    "\"loginRequired\":true",
+    "login-modal-required",
+];
+
+/// Classify an upstream response as a challenge page, if it is one.
+///
+/// Captions, bios and titles can quote any marker, so markers are only looked
+/// for in documents that aren't the `expected` content at all.
+pub fn detect(status: StatusCode, final_url: &Url, body: &str, expected: Expected) -> Option<ChallengeKind> {
+    let path = final_url.path();
+    
+    if path.contains("captcha") || path.starts_with("/verify") {
+        return Some(ChallengeKind::Captcha);
+    }
+    if path.starts_with("/_waf/") {
+        return Some(ChallengeKind::Waf);
+    }
+    if path.starts_with("/login") {
+        return Some(ChallengeKind::LoginWall);
+    }
+    
+    match expected {
+        Expected::Page if contains_any(body, PAGE_DATA_SCRIPTS) => return None,
+        Expected::Json => {
+            // Only the top level shape counts, never the strings inside
+            if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+                let login_required = json.get("loginRequired").and_then(|v| v.as_bool()) == Some(true);
+                return login_required.then_some(ChallengeKind::LoginWall);
+            }
+        }
+        Expected::Page => {}
+    }
+    
+    if contains_any(body, CAPTCHA_MARKERS) {
+        return Some(ChallengeKind::Captcha);
+    }
+    
+    // WAF pages come back as 403/405 or as tiny JS challenge documents
+    if contains_any(body, WAF_MARKERS)
+        && (status == StatusCode::FORBIDDEN || status == StatusCode::METHOD_NOT_ALLOWED || body.len() < 16 * 1024)
+    {
+        return Some(ChallengeKind::Waf);
+    }
+    
+    if contains_any(body, LOGIN_MARKERS) {
+        return Some(ChallengeKind::LoginWall);
+    }
+    
+    None
+}
+
+fn contains_any(body: &str, markers: &[&str]) -> bool {
+    markers.iter().any(|marker| body.contains(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn detect_at(path: &str, status: u16, body: &str, expected: Expected) -> Option<ChallengeKind> {
+        let url = Url::parse("https://www.tiktok.com").unwrap().join(path).unwrap();
+        detect(StatusCode::from_u16(status).unwrap(), &url, body, expected)
+    }
+    
+    #[test]
+    fn challenge_documents() {
+        let cases = [
+            ("/captcha/verify", 200, "", Expected::Page, Some(ChallengeKind::Captcha)),
+            ("/_waf/check", 200, "", Expected::Json, Some(ChallengeKind::Waf)),
+            ("/login?redirect_url=x", 200, "", Expected::Page, Some(ChallengeKind::LoginWall)),
+            ("/@a", 200, "<div id=\"captcha_container\"></div>", Expected::Page, Some(ChallengeKind::Captcha)),
+            ("/@a", 403, "<h1>Access Denied</h1>", Expected::Page, Some(ChallengeKind::Waf)),
+            ("/api/post/item_list/", 200, "<script src=\"/_waf/x.js\"></script>", Expected::Json, Some(ChallengeKind::Waf)),
+            ("/api/post/item_list/", 200, "{\"loginRequired\":true}", Expected::Json, Some(ChallengeKind::LoginWall)),
+            ("/@a", 200, "<html>nothing here</html>", Expected::Page, None),
+        ];
+        
+        for (path, status, body, expected, kind) in cases {
+            assert_eq!(detect_at(path, status, body, expected), kind, "{} {}", path, body);
+        }
+    }
+    
+    #[test]
+    fn user_text_is_not_a_challenge() {
+        let page = r#"<script id="__UNIVERSAL_DATA_FOR_REHYDRATION__" type="application/json">
+            {"desc":"captcha_container secsdk-captcha Access Denied /_waf/ \"loginRequired\":true"}</script>"#;
+        assert_eq!(detect_at("/@a/video/1", 200, page, Expected::Page), None);
+        
+        let json = r#"{"itemList":[{"desc":"captcha-verify verify-bar-close"}],"title":"Access Denied /_waf/"}"#;
+        assert_eq!(detect_at("/api/post/item_list/", 200, json, Expected::Json), None);
+        assert_eq!(detect_at("/oembed", 200, json, Expected::Json), None);
+    }
+}
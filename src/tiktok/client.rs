@@ -1,95 +1,397 @@
+use async_trait::async_trait;
 use once_cell::sync::Lazy;
-use reqwest::Client;
+use reqwest::{Client, Proxy, Response};
+use std::sync::Arc;
+use url::Url;
 
 use crate::error::AppError;
+use crate::metrics;
+use super::challenge::{self, ChallengeKind, Expected};
+use super::link::TikTokLink;
+use super::media_policy::{MediaUrlPolicy, PublicResolver};
 use super::parser;
-use super::types::{UserInfo, VideoInfo, TagInfo};
+use super::proxy_pool::ProxyPool;
+use super::retry::{self, CircuitBreaker, RetryPolicy};
+use super::session::{Session, SessionManager};
+use super::source::TikTokSource;
+use super::types::{Degradable, Extraction, UserInfo, VideoInfo, VideoPage, VideoSource, TagInfo, MusicInfo};
 
 static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
-    Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .expect("Failed to create HTTP client")
+    build_http_client(None).expect("Failed to create HTTP client")
 });
 
+/// Client with the settings every upstream request uses, optionally through a proxy.
+/// Redirects are followed by [`ProxyPool::get`] so cookies set along the way are seen.
+pub fn build_http_client(proxy: Option<Proxy>) -> reqwest::Result<Client> {
+    let builder = Client::builder()
+        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(std::time::Duration::from_secs(30));
+    
+    match proxy {
+        Some(proxy) => builder.proxy(proxy).build(),
+        None => builder.build(),
+    }
+}
+
+/// Client for the media proxy: redirects and DNS answers are held to `policy`
+pub fn build_media_client(proxy: Option<Proxy>, policy: Arc<MediaUrlPolicy>) -> reqwest::Result<Client> {
+    let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= 10 {
+            attempt.error("too many redirects")
+        } else if policy.allows(attempt.url()) {
+            attempt.follow()
+        } else {
+            tracing::warn!("Blocked media redirect to {}", attempt.url());
+            attempt.error("redirect to a disallowed URL")
+        }
+    });
+    
+    let builder = Client::builder()
+        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+        .redirect(redirect)
+        .dns_resolver(Arc::new(PublicResolver))
+        .timeout(std::time::Duration::from_secs(30));
+    
+    match proxy {
+        Some(proxy) => builder.proxy(proxy).build(),
+        None => builder.build(),
+    }
+}
+
 pub fn get_http_client() -> &'static Client {
     &HTTP_CLIENT
 }
 
-/// Fetch user profile and videos
-pub async fn fetch_user(username: &str) -> Result<UserInfo, AppError> {
-    let url = format!("https://www.tiktok.com/@{}", username);
-    
-    let response = HTTP_CLIENT
-        .get(&url)
-        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
-        .header("Accept-Language", "en-US,en;q=0.9")
-        .send()
+const HTML_ACCEPT: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+const JSON_ACCEPT: &str = "application/json, text/plain, */*";
+
+/// Number of videos requested per item list page
+const PAGE_SIZE: u32 = 30;
+
+/// Scrapes TikTok's web pages and item list endpoints
+#[derive(Debug, Clone)]
+pub struct WebSource {
+    pool: Arc<ProxyPool>,
+    sessions: Arc<SessionManager>,
+    base_url: String,
+    retry: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl WebSource {
+    /// `base_url` is normally `https://www.tiktok.com`, but can point at a mirror or mock server
+    pub fn new(
+        base_url: &str,
+        pool: Arc<ProxyPool>,
+        sessions: Arc<SessionManager>,
+        retry: RetryPolicy,
+        breaker: CircuitBreaker,
+    ) -> Self {
+        Self {
+            pool,
+            sessions,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            retry,
+            breaker: Arc::new(breaker),
+        }
+    }
+    
+    /// GET with retries and the endpoint's circuit breaker, carrying the session's
+    /// cookies and following redirects while `follow` agrees
+    async fn get(
+        &self,
+        endpoint: &'static str,
+        url: &str,
+        accept: &str,
+        referer: Option<&str>,
+        session: Option<&Session>,
+        follow: impl Fn(&Url) -> bool,
+    ) -> Result<Response, AppError> {
+        let session_key = session.map(Session::key);
+        
+        retry::send_with_retry(&self.retry, &self.breaker, endpoint, || async {
+            self.pool
+                .get(
+                    url,
+                    session_key.as_deref(),
+                    |request, hop| {
+                        let mut request = request
+                            .header("Accept", accept)
+                            .header("Accept-Language", "en-US,en;q=0.9");
+                        if let Some(referer) = referer {
+                            request = request.header("Referer", referer);
+                        }
+                        // Read per hop, a redirect may just have set some
+                        if let Some(cookies) = session.and_then(|session| self.sessions.cookie_header_for(session, hop)) {
+                            request = request.header(reqwest::header::COOKIE, cookies);
+                        }
+                        request
+                    },
+                    |response| {
+                        if let Some(session) = session {
+                            self.sessions.store_cookies_from(session, response);
+                        }
+                    },
+                    &follow,
+                )
+                .await
+        })
         .await
-        .map_err(|e| AppError::FetchError(e.to_string()))?;
+    }
     
-    if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(AppError::NotFound);
+    /// Fetch an HTML page, mapping 404, challenges and other failures to `AppError`
+    async fn fetch_page(&self, endpoint: &'static str, url: &str) -> Result<String, AppError> {
+        let session = self.sessions.acquire().await;
+        let response = self.get(endpoint, url, HTML_ACCEPT, None, session.as_deref(), |_| true).await?;
+        
+        self.read_body(response, Expected::Page, session.as_deref()).await
     }
     
-    if !response.status().is_success() {
-        return Err(AppError::FetchError(format!("Status: {}", response.status())));
+    /// Fetch a JSON API endpoint
+    async fn fetch_json(&self, endpoint: &'static str, url: &str, referer: &str) -> Result<serde_json::Value, AppError> {
+        let session = self.sessions.acquire().await;
+        let response = self.get(endpoint, url, JSON_ACCEPT, Some(referer), session.as_deref(), |_| true).await?;
+        
+        // TikTok answers with an empty body when it refuses the request
+        let body = self.read_body(response, Expected::Json, session.as_deref()).await?;
+        serde_json::from_str(&body).map_err(|_| AppError::ParseError)
     }
     
-    let html = response.text().await.map_err(|e| AppError::FetchError(e.to_string()))?;
+    /// Read a response body, turning 404s, challenge pages and error statuses into `AppError`
+    async fn read_body(&self, response: Response, expected: Expected, session: Option<&Session>) -> Result<String, AppError> {
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(AppError::NotFound);
+        }
+        
+        let final_url = response.url().clone();
+        let proxy = ProxyPool::proxy_used(&response);
+        let body = response.text().await.map_err(|e| AppError::FetchError(e.to_string()))?;
+        
+        if let Some(kind) = challenge::detect(status, &final_url, &body, expected) {
+            tracing::warn!("TikTok served a {} for {}", kind, final_url.path());
+            metrics::record_challenge(kind);
+            
+            // A firewall block is about the exit IP, the rest about the session
+            if let (ChallengeKind::Waf, Some(proxy)) = (kind, &proxy) {
+                self.pool.eject(proxy);
+            }
+            if let Some(session) = session {
+                self.sessions.retire(session);
+            }
+            return Err(AppError::UpstreamChallenge(kind));
+        }
+        
+        if !status.is_success() {
+            return Err(AppError::FetchError(format!("Status: {}", status)));
+        }
+        
+        Ok(body)
+    }
     
-    parser::parse_user_page(&html, username)
-}
-
-/// Fetch single video
-pub async fn fetch_video(video_id: &str) -> Result<VideoInfo, AppError> {
-    // Try to fetch the video page directly
-    let url = format!("https://www.tiktok.com/video/{}", video_id);
-    
-    let response = HTTP_CLIENT
-        .get(&url)
-        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
-        .header("Accept-Language", "en-US,en;q=0.9")
-        .send()
-        .await
-        .map_err(|e| AppError::FetchError(e.to_string()))?;
+    async fn fetch_item_list(&self, url: &str, referer: &str) -> Result<VideoPage, AppError> {
+        let json = self.fetch_json("item_list", url, referer).await?;
+        parser::parse_item_list(&json).map_err(|e| AppError::ExtractionFailed {
+            extractor: "item list",
+            reason: format!("unexpected JSON at `{}`: {}", e.path, e.message),
+        })
+    }
     
-    if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(AppError::NotFound);
+    /// Fallback: the `/embed/v2/{id}` player page
+    async fn fetch_video_embed(&self, video_id: &str) -> Result<Option<VideoInfo>, AppError> {
+        let url = format!("{}/embed/v2/{}", self.base_url, video_id);
+        let html = self.fetch_page("embed", &url).await?;
+        
+        Ok(parser::parse_embed_page(&html, video_id))
     }
     
-    if !response.status().is_success() {
-        return Err(AppError::FetchError(format!("Status: {}", response.status())));
+    /// Last resort: the oEmbed endpoint, which only has metadata and a cover
+    async fn fetch_video_oembed(&self, video_id: &str) -> Result<Option<VideoInfo>, AppError> {
+        let video_url = format!("https://www.tiktok.com/video/{}", video_id);
+        let url = format!("{}/oembed?url={}", self.base_url, urlencoding::encode(&video_url));
+        let json = self.fetch_json("oembed", &url, &self.base_url).await?;
+        
+        Ok(parser::parse_oembed(&json, video_id))
     }
     
-    let html = response.text().await.map_err(|e| AppError::FetchError(e.to_string()))?;
+    /// Fetch one page of a user's posts from the item list endpoint
+    async fn fetch_user_videos(&self, sec_uid: &str, username: &str, cursor: Option<&str>) -> Result<VideoPage, AppError> {
+        let url = format!(
+            "{}/api/post/item_list/?aid=1988&count={}&cursor={}&secUid={}",
+            self.base_url,
+            PAGE_SIZE,
+            urlencoding::encode(cursor.unwrap_or("0")),
+            urlencoding::encode(sec_uid),
+        );
+        
+        self.fetch_item_list(&url, &format!("{}/@{}", self.base_url, username)).await
+    }
     
-    parser::parse_video_page(&html, video_id)
+    /// Fetch one page of a hashtag's videos from the challenge item list endpoint
+    async fn fetch_tag_videos(&self, challenge_id: &str, tag_name: &str, cursor: Option<&str>) -> Result<VideoPage, AppError> {
+        let url = format!(
+            "{}/api/challenge/item_list/?aid=1988&count={}&cursor={}&challengeID={}",
+            self.base_url,
+            PAGE_SIZE,
+            urlencoding::encode(cursor.unwrap_or("0")),
+            urlencoding::encode(challenge_id),
+        );
+        
+        self.fetch_item_list(&url, &format!("{}/tag/{}", self.base_url, urlencoding::encode(tag_name))).await
+    }
 }
 
-/// Fetch tag/hashtag videos
-pub async fn fetch_tag(tag_name: &str) -> Result<TagInfo, AppError> {
-    let url = format!("https://www.tiktok.com/tag/{}", tag_name);
-    
-    let response = HTTP_CLIENT
-        .get(&url)
-        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
-        .header("Accept-Language", "en-US,en;q=0.9")
-        .send()
-        .await
-        .map_err(|e| AppError::FetchError(e.to_string()))?;
+#[async_trait]
+impl TikTokSource for WebSource {
+    async fn fetch_user(&self, username: &str, cursor: Option<&str>) -> Result<UserInfo, AppError> {
+        let url = format!("{}/@{}", self.base_url, username);
+        let html = self.fetch_page("user", &url).await?;
+        
+        let mut user = parser::parse_user_page(&html, username).into_result()?;
+        
+        if !user.sec_uid.is_empty() {
+            // A missing video grid shouldn't take the whole profile down
+            match self.fetch_user_videos(&user.sec_uid, username, cursor).await {
+                Ok(page) => {
+                    user.videos = page.videos;
+                    user.cursor = page.cursor;
+                    user.has_more = page.has_more;
+                }
+                Err(e) => {
+                    tracing::warn!("Could not fetch videos for user {}: {}", username, e);
+                    user.mark_missing(&["videos"]);
+                }
+            }
+        }
+        
+        Ok(user)
+    }
+    
+    async fn fetch_profile(&self, username: &str) -> Result<UserInfo, AppError> {
+        let url = format!("{}/@{}", self.base_url, username);
+        let html = self.fetch_page("user", &url).await?;
+        
+        parser::parse_user_page(&html, username).into_result()
+    }
     
-    if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(AppError::NotFound);
+    async fn fetch_video(&self, video_id: &str) -> Result<VideoInfo, AppError> {
+        // Try to fetch the video page directly
+        let url = format!("{}/video/{}", self.base_url, video_id);
+        let mut page_error = None;
+        let mut video = match self.fetch_page("video", &url).await {
+            Ok(html) => match parser::parse_video_page(&html, video_id) {
+                Extraction::Complete(video) | Extraction::Partial(video, _) => Some(video),
+                // The fallbacks would only be told the same
+                Extraction::NotFound => return Err(AppError::NotFound),
+                Extraction::Failed { extractor, reason } => {
+                    tracing::warn!("Could not extract {} for {}: {}", extractor, video_id, reason);
+                    page_error = Some(AppError::ExtractionFailed { extractor, reason });
+                    None
+                }
+            },
+            Err(AppError::NotFound) => return Err(AppError::NotFound),
+            // The fallbacks live on the same host, don't pile onto a rate limit
+            Err(e @ AppError::RateLimited { .. }) => return Err(e),
+            Err(e) => {
+                tracing::warn!("Video page fetch failed for {}: {}", video_id, e);
+                page_error = Some(e);
+                None
+            }
+        };
+        
+        // Fall back to the embed page, then oEmbed, topping up whatever is still missing
+        if !video.as_ref().is_some_and(VideoInfo::is_complete) {
+            match self.fetch_video_embed(video_id).await {
+                Ok(Some(embed)) => merge_video(&mut video, embed),
+                Ok(None) => tracing::warn!("Could not parse embed page for video: {}", video_id),
+                Err(e) => tracing::warn!("Embed page fetch failed for {}: {}", video_id, e),
+            }
+        }
+        
+        if !video.as_ref().is_some_and(VideoInfo::is_complete) {
+            match self.fetch_video_oembed(video_id).await {
+                Ok(Some(oembed)) => merge_video(&mut video, oembed),
+                Ok(None) => tracing::warn!("Could not parse oEmbed data for video: {}", video_id),
+                Err(e) => tracing::warn!("oEmbed fetch failed for {}: {}", video_id, e),
+            }
+        }
+        
+        match (video, page_error) {
+            (Some(mut video), _) => {
+                if video.source != VideoSource::Page {
+                    tracing::info!("Video {} loaded from {}", video_id, video.source.label());
+                }
+                // Whatever the fallbacks couldn't fill in either
+                let missing = video.missing_fields();
+                video.mark_missing(&missing);
+                if video.source == VideoSource::OEmbed {
+                    video.mark_missing(&["stats"]);
+                }
+                Ok(video)
+            }
+            // Nothing to show, report why the page failed instead of a placeholder
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(AppError::ExtractionFailed {
+                extractor: "video page",
+                reason: "no extractor found the video".to_string(),
+            }),
+        }
     }
     
-    if !response.status().is_success() {
-        return Err(AppError::FetchError(format!("Status: {}", response.status())));
+    async fn fetch_tag(&self, tag_name: &str, cursor: Option<&str>) -> Result<TagInfo, AppError> {
+        let url = format!("{}/tag/{}", self.base_url, urlencoding::encode(tag_name));
+        let html = self.fetch_page("tag", &url).await?;
+        
+        let mut tag = parser::parse_tag_page(&html, tag_name).into_result()?;
+        
+        if !tag.id.is_empty() {
+            match self.fetch_tag_videos(&tag.id, tag_name, cursor).await {
+                Ok(page) => {
+                    tag.videos = page.videos;
+                    tag.cursor = page.cursor;
+                    tag.has_more = page.has_more;
+                }
+                Err(e) => {
+                    tracing::warn!("Could not fetch videos for tag {}: {}", tag_name, e);
+                    tag.mark_missing(&["videos"]);
+                }
+            }
+        }
+        
+        Ok(tag)
     }
     
-    let html = response.text().await.map_err(|e| AppError::FetchError(e.to_string()))?;
+    async fn fetch_music(&self, slug: &str) -> Result<MusicInfo, AppError> {
+        let url = format!("{}/music/{}", self.base_url, urlencoding::encode(slug));
+        let html = self.fetch_page("music", &url).await?;
+        
+        parser::parse_music_page(&html).into_result()
+    }
     
-    parser::parse_tag_page(&html, tag_name)
+    async fn resolve_short_link(&self, url: &Url) -> Result<Url, AppError> {
+        // Stop at the first hop naming the post, the chain may go on to a login,
+        // captcha or region page that doesn't
+        let canonical = |url: &Url| TikTokLink::parse(url.as_str()).is_some_and(|link| !matches!(link, TikTokLink::ShortLink(_)));
+        
+        let session = self.sessions.acquire().await;
+        let response = self
+            .get("short_link", url.as_str(), HTML_ACCEPT, None, session.as_deref(), |next| !canonical(next))
+            .await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(AppError::NotFound);
+        }
+        
+        // Only the location matters, the body is never read
+        Ok(ProxyPool::redirect_location(&response).unwrap_or_else(|| response.url().clone()))
+    }
+}
+
+/// The first extractor to succeed decides `source`, later ones only fill gaps
+fn merge_video(video: &mut Option<VideoInfo>, found: VideoInfo) {
+    match video {
+        Some(video) => video.fill_missing(found),
+        None => *video = Some(found),
+    }
 }
@@ -0,0 +1,168 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use url::Url;
+
+use crate::error::AppError;
+use super::source::TikTokSource;
+use super::types::{UserInfo, VideoInfo, TagInfo, MusicInfo};
+
+/// Identifies one upstream fetch: (kind, id, cursor)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FetchKey {
+    pub kind: &'static str,
+    pub id: String,
+    pub cursor: Option<String>,
+}
+
+impl FetchKey {
+    pub fn new(kind: &'static str, id: &str, cursor: Option<&str>) -> Self {
+        Self {
+            kind,
+            id: id.to_string(),
+            cursor: cursor.map(String::from),
+        }
+    }
+}
+
+type InFlight<T> = Arc<Mutex<HashMap<FetchKey, broadcast::Sender<Result<T, AppError>>>>>;
+
+/// Single-flight deduplication: concurrent calls with the same key share one fetch
+pub struct SingleFlight<T> {
+    in_flight: InFlight<T>,
+}
+
+impl<T: Clone + Send + 'static> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self { in_flight: Arc::new(Mutex::new(HashMap::new())) }
+    }
+    
+    /// Run `fetch` unless an identical one is already running, then share its result.
+    /// The fetch runs in its own task, so a caller going away doesn't cancel it for the others.
+    pub async fn run<F, Fut>(&self, key: FetchKey, fetch: F) -> Result<T, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>> + Send + 'static,
+    {
+        let mut receiver = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            
+            match in_flight.get(&key) {
+                Some(sender) => {
+                    tracing::debug!("Joining in-flight fetch: {:?}", key);
+                    sender.subscribe()
+                }
+                None => {
+                    let (sender, receiver) = broadcast::channel(1);
+                    in_flight.insert(key.clone(), sender.clone());
+                    
+                    let guard = InFlightGuard { in_flight: Arc::clone(&self.in_flight), key };
+                    let future = fetch();
+                    tokio::spawn(async move {
+                        let result = future.await;
+                        // Unregister before publishing so late callers start a fresh fetch
+                        drop(guard);
+                        let _ = sender.send(result);
+                    });
+                    
+                    receiver
+                }
+            }
+        };
+        
+        // The sender only disappears without a value if the fetch task panicked
+        receiver.recv().await.unwrap_or(Err(AppError::Internal))
+    }
+}
+
+/// Removes the key when the fetch task finishes, or unwinds
+struct InFlightGuard<T> {
+    in_flight: InFlight<T>,
+    key: FetchKey,
+}
+
+impl<T> Drop for InFlightGuard<T> {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+/// Wraps a source so identical concurrent requests hit upstream once
+pub struct CoalescingSource {
+    inner: Arc<dyn TikTokSource>,
+    users: SingleFlight<UserInfo>,
+    videos: SingleFlight<VideoInfo>,
+    tags: SingleFlight<TagInfo>,
+    music: SingleFlight<MusicInfo>,
+}
+
+impl CoalescingSource {
+    pub fn new(inner: Arc<dyn TikTokSource>) -> Self {
+        Self {
+            inner,
+            users: SingleFlight::new(),
+            videos: SingleFlight::new(),
+            tags: SingleFlight::new(),
+            music: SingleFlight::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl TikTokSource for CoalescingSource {
+    async fn fetch_user(&self, username: &str, cursor: Option<&str>) -> Result<UserInfo, AppError> {
+        let key = FetchKey::new("user", username, cursor);
+        let (inner, username, cursor) = (Arc::clone(&self.inner), username.to_string(), cursor.map(String::from));
+        self.users
+            .run(key, move || async move { inner.fetch_user(&username, cursor.as_deref()).await })
+            .await
+    }
+    
+    async fn fetch_profile(&self, username: &str) -> Result<UserInfo, AppError> {
+        let key = FetchKey::new("profile", username, None);
+        let (inner, username) = (Arc::clone(&self.inner), username.to_string());
+        self.users
+            .run(key, move || async move { inner.fetch_profile(&username).await })
+            .await
+    }
+    
+    async fn fetch_video(&self, video_id: &str) -> Result<VideoInfo, AppError> {
+        let key = FetchKey::new("video", video_id, None);
+        let (inner, video_id) = (Arc::clone(&self.inner), video_id.to_string());
+        self.videos
+            .run(key, move || async move { inner.fetch_video(&video_id).await })
+            .await
+    }
+    
+    async fn fetch_tag(&self, tag_name: &str, cursor: Option<&str>) -> Result<TagInfo, AppError> {
+        let key = FetchKey::new("tag", tag_name, cursor);
+        let (inner, tag_name, cursor) = (Arc::clone(&self.inner), tag_name.to_string(), cursor.map(String::from));
+        self.tags
+            .run(key, move || async move { inner.fetch_tag(&tag_name, cursor.as_deref()).await })
+            .await
+    }
+    
+    async fn fetch_music(&self, slug: &str) -> Result<MusicInfo, AppError> {
+        let key = FetchKey::new("music", slug, None);
+        let (inner, slug) = (Arc::clone(&self.inner), slug.to_string());
+        self.music
+            .run(key, move || async move { inner.fetch_music(&slug).await })
+            .await
+    }
+    
+    async fn resolve_short_link(&self, url: &Url) -> Result<Url, AppError> {
+        self.inner.resolve_short_link(url).await
+    }
+    
+    fn invalidate_video(&self, video_id: &str) {
+        self.inner.invalidate_video(video_id);
+    }
+    
+    fn invalidate_user(&self, username: &str) {
+        self.inner.invalidate_user(username);
+    }
+}
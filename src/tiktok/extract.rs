@@ -0,0 +1,210 @@
+use once_cell::sync::Lazy;
+use scraper::{Html, Selector};
+
+use super::schema::{self, DefaultScope, ItemInfo, LdNode, PageState, SchemaError, UserDetail, VideoDetail};
+
+static REHYDRATION: Lazy<Selector> = Lazy::new(|| selector("script#__UNIVERSAL_DATA_FOR_REHYDRATION__"));
+static SIGI_STATE: Lazy<Selector> = Lazy::new(|| selector("script#SIGI_STATE"));
+pub static NEXT_DATA: Lazy<Selector> = Lazy::new(|| selector("script#__NEXT_DATA__"));
+pub static FRONTITY_STATE: Lazy<Selector> = Lazy::new(|| selector("script#__FRONTITY_CONNECT_STATE__"));
+static LD_JSON: Lazy<Selector> = Lazy::new(|| selector(r#"script[type="application/ld+json"]"#));
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("selectors are valid")
+}
+
+/// Where TikTok has kept page data over the years
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// `__UNIVERSAL_DATA_FOR_REHYDRATION__`, current pages
+    Rehydration,
+    /// `SIGI_STATE`, the module layout before that
+    SigiState,
+    /// `__NEXT_DATA__` from the Next.js pages
+    NextData,
+    /// schema.org metadata meant for search engines, no video list but often
+    /// still there when everything else is missing
+    LdJson,
+}
+
+/// Tried in this order, the first that has what a page needs wins
+pub const STRATEGIES: [Strategy; 4] = [
+    Strategy::Rehydration,
+    Strategy::SigiState,
+    Strategy::NextData,
+    Strategy::LdJson,
+];
+
+impl Strategy {
+    pub fn label(self) -> &'static str {
+        match self {
+            Strategy::Rehydration => "rehydration data",
+            Strategy::SigiState => "SIGI_STATE",
+            Strategy::NextData => "__NEXT_DATA__",
+            Strategy::LdJson => "ld+json",
+        }
+    }
+    
+    /// Page state in this layout, `None` when the page doesn't use it
+    pub fn page_state(self, document: &Html) -> Option<Result<PageState, String>> {
+        let state = match self {
+            Strategy::Rehydration => decode(&script_text(document, &REHYDRATION)?),
+            Strategy::SigiState => decode(&script_text(document, &SIGI_STATE)?),
+            Strategy::NextData => decode::<schema::NextData>(&script_text(document, &NEXT_DATA)?).map(PageState::from),
+            Strategy::LdJson => ld_page_state(document)?,
+        };
+        
+        Some(state.map_err(|e| format!("unexpected JSON at `{}`: {}", e.path, e.message)))
+    }
+}
+
+fn decode<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, SchemaError> {
+    schema::decode("page", json)
+}
+
+/// Text of the first `<script>` matching `selector`
+pub fn script_text(document: &Html, selector: &Selector) -> Option<String> {
+    document
+        .select(selector)
+        .next()
+        .map(|script| script.text().collect())
+}
+
+/// The first video and person described by any ld+json script
+fn ld_page_state(document: &Html) -> Option<Result<PageState, SchemaError>> {
+    let mut scripts = document.select(&LD_JSON).peekable();
+    scripts.peek()?;
+    
+    let mut nodes = Vec::new();
+    for script in scripts {
+        let text: String = script.text().collect();
+        match decode::<schema::LdDocument>(&text) {
+            Ok(document) => document.0.into_iter().for_each(|node| flatten(node, &mut nodes)),
+            Err(e) if nodes.is_empty() => return Some(Err(e)),
+            Err(e) => tracing::debug!("Skipping ld+json script: {}", e),
+        }
+    }
+    
+    let mut scope = DefaultScope::default();
+    for node in nodes {
+        if node.is("VideoObject") && scope.video_detail.is_none() {
+            scope.video_detail = Some(VideoDetail {
+                item_info: Some(ItemInfo { item_struct: ld_item(node) }),
+                ..VideoDetail::default()
+            });
+        } else if node.is("Person") && scope.user_detail.is_none() {
+            scope.user_detail = Some(UserDetail {
+                user_info: Some(ld_user(node)),
+                ..UserDetail::default()
+            });
+        }
+    }
+    
+    Some(Ok(PageState {
+        default_scope: Some(scope),
+        ..PageState::default()
+    }))
+}
+
+/// Pull nodes out of `@graph` lists and `ProfilePage`s
+fn flatten(mut node: LdNode, out: &mut Vec<LdNode>) {
+    for child in std::mem::take(&mut node.graph) {
+        flatten(child, out);
+    }
+    if let Some(main) = node.main_entity.take() {
+        flatten(*main, out);
+    }
+    out.push(node);
+}
+
+fn ld_item(node: LdNode) -> schema::Item {
+    let count = |action: &str| interaction_count(&node, action);
+    let stats = schema::ItemStats {
+        digg_count: count("LikeAction"),
+        comment_count: count("CommentAction"),
+        share_count: count("ShareAction"),
+        play_count: count("WatchAction"),
+    };
+    let author = node.author.as_deref().map(|author| schema::Author {
+        unique_id: ld_handle(author),
+        nickname: author.name.clone(),
+        avatar_medium: first_url(&author.image),
+    });
+    
+    schema::Item {
+        // `url` ends in the video ID, the page fills it in otherwise
+        id: node.url.rsplit('/').next().filter(|id| id.chars().all(|c| c.is_ascii_digit())).unwrap_or("").to_string(),
+        desc: if node.description.is_empty() { node.name.clone() } else { node.description.clone() },
+        create_time: unix_time(&node.upload_date).unwrap_or(0),
+        author: author.unwrap_or_default(),
+        stats,
+        video: schema::Video {
+            play_addr: node.content_url.clone(),
+            cover: first_url(&node.thumbnail_url),
+            ..schema::Video::default()
+        },
+        music: None,
+        image_post: None,
+    }
+}
+
+fn ld_user(node: LdNode) -> schema::UserInfo {
+    // No counters at all means we don't know them, rather than that they're zero
+    let stats = (!node.interaction_statistic.is_empty()).then(|| schema::UserStats {
+        follower_count: interaction_count(&node, "FollowAction"),
+        heart_count: interaction_count(&node, "LikeAction"),
+        ..schema::UserStats::default()
+    });
+    
+    schema::UserInfo {
+        user: schema::User {
+            id: String::new(),
+            unique_id: ld_handle(&node),
+            nickname: node.name.clone(),
+            signature: node.description.clone(),
+            avatar_larger: first_url(&node.image),
+            avatar_medium: String::new(),
+            sec_uid: String::new(),
+        },
+        stats,
+    }
+}
+
+/// Username from `alternateName` (`@name`) or a profile URL
+fn ld_handle(node: &LdNode) -> String {
+    let from_url = node.url.split('/').find_map(|segment| segment.strip_prefix('@'));
+    
+    match node.alternate_name.trim_start_matches('@') {
+        "" => from_url.unwrap_or("").to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn interaction_count(node: &LdNode, action: &str) -> u64 {
+    node.interaction_statistic
+        .iter()
+        .find(|stat| stat.interaction_type.as_ref().is_some_and(|kind| kind.name() == action))
+        .map(|stat| stat.user_interaction_count)
+        .unwrap_or(0)
+}
+
+fn first_url(urls: &[schema::LdUrl]) -> String {
+    urls.first().map(|url| url.as_str().to_string()).unwrap_or_default()
+}
+
+/// Seconds since the epoch for an ISO 8601 `uploadDate`, read as UTC
+fn unix_time(date: &str) -> Option<u64> {
+    let field = |range: std::ops::Range<usize>| -> Option<i64> { date.get(range)?.parse().ok() };
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13).unwrap_or(0), field(14..16).unwrap_or(0), field(17..19).unwrap_or(0));
+    
+    // Days from the civil calendar, as in Howard Hinnant's `days_from_civil`
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    
+    u64::try_from(days * 86_400 + hour * 3600 + minute * 60 + second).ok()
+}
@@ -0,0 +1,359 @@
+use url::Url;
+
+/// Query parameters TikTok appends to shared links for tracking
+const TRACKING_PARAMS: &[&str] = &[
+    "_r",
+    "_t",
+    "_d",
+    "is_from_webapp",
+    "sender_device",
+    "sender_web_id",
+    "is_copy_url",
+    "share_app_id",
+    "share_link_id",
+    "social_sharing",
+    "source",
+    "tt_from",
+    "u_code",
+    "preview_pb",
+    "checksum",
+    "timestamp",
+    "user_id",
+    "utm_campaign",
+    "utm_medium",
+    "utm_source",
+    "web_id",
+    "refer",
+    "lang",
+];
+
+/// A TikTok link, parsed from any of the URL shapes TikTok hands out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TikTokLink {
+    /// `/@username`
+    User(String),
+    /// `/@username/video/{id}`, `/video/{id}`, `m.tiktok.com/v/{id}.html`
+    Video { username: Option<String>, id: String },
+    /// `/@username/photo/{id}`
+    Photo { username: Option<String>, id: String },
+    /// `/tag/{name}`, `/discover/{topic}` as the hashtag of the same words
+    Tag(String),
+    /// `/music/{title}-{id}`
+    Music(String),
+    /// `vm.tiktok.com/{code}`, `vt.tiktok.com/{code}`, `/t/{code}`, resolved upstream
+    ShortLink(Url),
+    /// `/embed/{id}`, `/embed/v2/{id}`
+    Embed(String),
+    /// `/@username/live`
+    Live(String),
+    /// `/@username/playlist/{name}-{id}`
+    Playlist { username: String, id: String },
+}
+
+impl TikTokLink {
+    /// Parse a pasted TikTok URL or app deep link
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let url = if input.contains("://") {
+            Url::parse(input).ok()?
+        } else {
+            Url::parse(&format!("https://{}", input)).ok()?
+        };
+        
+        match url.scheme() {
+            "http" | "https" => Self::from_web_url(url),
+            // snssdk1233://aweme/detail/{id}, tiktok://aweme/detail/{id}
+            scheme if scheme.starts_with("snssdk") || scheme == "tiktok" => Self::from_deep_link(&url),
+            _ => None,
+        }
+    }
+    
+    fn from_web_url(mut url: Url) -> Option<Self> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        if host != "tiktok.com" && !host.ends_with(".tiktok.com") {
+            return None;
+        }
+        
+        strip_tracking_params(&mut url);
+        url.set_fragment(None);
+        
+        let segments: Vec<String> = url
+            .path_segments()?
+            .filter(|s| !s.is_empty())
+            .map(decode_segment)
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        
+        if host == "vm.tiktok.com" || host == "vt.tiktok.com" {
+            return match segments.as_slice() {
+                [code] if is_short_code(code) => Some(TikTokLink::ShortLink(url)),
+                _ => None,
+            };
+        }
+        
+        let link = match segments.as_slice() {
+            [user, rest @ ..] if user.starts_with('@') => {
+                let username = user_name(user)?;
+                match rest {
+                    ["video", id, ..] => video_id(id).map(|id| {
+                        TikTokLink::Video { username: Some(username), id }
+                    }),
+                    ["photo", id, ..] => video_id(id).map(|id| {
+                        TikTokLink::Photo { username: Some(username), id }
+                    }),
+                    ["live", ..] => Some(TikTokLink::Live(username)),
+                    ["playlist", slug, ..] => trailing_id(slug).map(|id| {
+                        TikTokLink::Playlist { username, id }
+                    }),
+                    _ => Some(TikTokLink::User(username)),
+                }
+            }
+            ["video", id, ..] | ["share", "video", id, ..] => video_id(id).map(|id| {
+                TikTokLink::Video { username: None, id }
+            }),
+            ["v", file] => file
+                .strip_suffix(".html")
+                .and_then(video_id)
+                .map(|id| TikTokLink::Video { username: None, id }),
+            ["embed", "v2", id, ..] | ["embed", id, ..] => video_id(id).map(TikTokLink::Embed),
+            ["tag", tag, ..] => Some(TikTokLink::Tag(tag.to_string())),
+            ["discover", topic, ..] => discover_tag(topic).map(TikTokLink::Tag),
+            ["music", slug, ..] if is_music_slug(slug) => Some(TikTokLink::Music(slug.to_string())),
+            ["t", code] if is_short_code(code) => Some(TikTokLink::ShortLink(url.clone())),
+            _ => None,
+        };
+        
+        // Some share pages only carry the video in the query string
+        link.or_else(|| {
+            url.query_pairs()
+                .find(|(key, _)| key == "share_item_id" || key == "item_id")
+                .and_then(|(_, id)| video_id(&id))
+                .map(|id| TikTokLink::Video { username: None, id })
+        })
+    }
+    
+    fn from_deep_link(url: &Url) -> Option<Self> {
+        let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        
+        match (url.host_str()?, segments.as_slice()) {
+            ("aweme", ["detail", id]) => video_id(id).map(|id| TikTokLink::Video { username: None, id }),
+            ("challenge", ["detail", tag]) => Some(TikTokLink::Tag(decode_segment(tag))),
+            _ => None,
+        }
+    }
+    
+    /// Path of the matching page on this instance
+    pub fn to_local_path(&self) -> String {
+        match self {
+            TikTokLink::User(username) | TikTokLink::Live(username) => {
+                format!("/@{}", urlencoding::encode(username))
+            }
+            TikTokLink::Playlist { username, .. } => format!("/@{}", urlencoding::encode(username)),
+            TikTokLink::Video { username: Some(username), id } => {
+                format!("/@{}/video/{}", urlencoding::encode(username), id)
+            }
+            TikTokLink::Photo { username: Some(username), id } => {
+                format!("/@{}/photo/{}", urlencoding::encode(username), id)
+            }
+            TikTokLink::Video { username: None, id }
+            | TikTokLink::Photo { username: None, id }
+            | TikTokLink::Embed(id) => format!("/video/{}", id),
+            TikTokLink::Tag(tag) => format!("/tag/{}", urlencoding::encode(tag)),
+            TikTokLink::Music(slug) => format!("/music/{}", urlencoding::encode(slug)),
+            TikTokLink::ShortLink(url) => format!("/redirect?url={}", urlencoding::encode(url.as_str())),
+        }
+    }
+}
+
+fn strip_tracking_params(url: &mut Url) {
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !TRACKING_PARAMS.contains(&key.as_ref()))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+}
+
+fn decode_segment(segment: &str) -> String {
+    urlencoding::decode(segment)
+        .map(|s| s.into_owned())
+        .unwrap_or_else(|_| segment.to_string())
+}
+
+fn user_name(segment: &str) -> Option<String> {
+    let name = segment.strip_prefix('@')?;
+    is_username(name).then(|| name.to_string())
+}
+
+/// TikTok usernames are letters, digits, `_` and `.`, up to 24 characters
+pub fn is_username(name: &str) -> bool {
+    (1..=24).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn video_id(segment: &str) -> Option<String> {
+    if !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()) {
+        Some(segment.to_string())
+    } else {
+        None
+    }
+}
+
+/// Playlist and music slugs end with `-{id}`
+fn trailing_id(slug: &str) -> Option<String> {
+    video_id(slug.rsplit('-').next()?)
+}
+
+/// Discover pages are keyword feeds, e.g. `/discover/funny-cat-videos`. The
+/// nearest thing to show is the hashtag of the same words run together.
+pub fn discover_tag(topic: &str) -> Option<String> {
+    let tag: String = topic.chars().filter(|c| c.is_alphanumeric() || *c == '_').collect();
+    (!tag.is_empty()).then_some(tag)
+}
+
+/// `{title}-{id}`, the title being words joined with dashes
+pub fn is_music_slug(slug: &str) -> bool {
+    let Some((title, id)) = slug.rsplit_once('-') else {
+        return false;
+    };
+    !id.is_empty()
+        && id.chars().all(|c| c.is_ascii_digit())
+        && title.chars().all(|c| c.is_alphanumeric() || c == '-')
+}
+
+fn is_short_code(code: &str) -> bool {
+    !code.is_empty() && code.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn video(username: Option<&str>, id: &str) -> Option<TikTokLink> {
+        Some(TikTokLink::Video { username: username.map(str::to_string), id: id.to_string() })
+    }
+    
+    fn short(url: &str) -> Option<TikTokLink> {
+        Some(TikTokLink::ShortLink(Url::parse(url).unwrap()))
+    }
+    
+    fn check(cases: &[(&str, Option<TikTokLink>)]) {
+        for (input, expected) in cases {
+            assert_eq!(&TikTokLink::parse(input), expected, "{}", input);
+        }
+    }
+    
+    #[test]
+    fn short_links() {
+        check(&[
+            ("https://vm.tiktok.com/ZMabc123/", short("https://vm.tiktok.com/ZMabc123/")),
+            ("https://vt.tiktok.com/ZSxyz789/", short("https://vt.tiktok.com/ZSxyz789/")),
+            ("vm.tiktok.com/ZMabc123", short("https://vm.tiktok.com/ZMabc123")),
+            ("https://VM.TikTok.com/ZMabc123/", short("https://vm.tiktok.com/ZMabc123/")),
+            ("https://vm.tiktok.com/ZMabc123/?_r=1&_t=8abc", short("https://vm.tiktok.com/ZMabc123/")),
+            ("https://www.tiktok.com/t/ZTRabc123/", short("https://www.tiktok.com/t/ZTRabc123/")),
+            ("https://tiktok.com/t/ZTRabc123", short("https://tiktok.com/t/ZTRabc123")),
+            ("https://vm.tiktok.com/", None),
+            ("https://vm.tiktok.com/ZM-abc/", None),
+            ("https://vm.tiktok.com/ZMabc/extra", None),
+            ("https://www.tiktok.com/t/", None),
+        ]);
+    }
+    
+    #[test]
+    fn videos() {
+        check(&[
+            ("https://www.tiktok.com/@scout2015/video/6718335390845095173", video(Some("scout2015"), "6718335390845095173")),
+            ("www.tiktok.com/@scout2015/video/6718335390845095173", video(Some("scout2015"), "6718335390845095173")),
+            ("http://tiktok.com/@scout2015/video/6718335390845095173/", video(Some("scout2015"), "6718335390845095173")),
+            ("  https://www.tiktok.com/@scout.2015_/video/1  ", video(Some("scout.2015_"), "1")),
+            ("https://www.tiktok.com/video/6718335390845095173", video(None, "6718335390845095173")),
+            ("https://www.tiktok.com/share/video/6718335390845095173", video(None, "6718335390845095173")),
+            ("https://www.tiktok.com/@scout2015/video/abc", None),
+            ("https://www.tiktok.com/@scout2015/video/", Some(TikTokLink::User("scout2015".to_string()))),
+            ("https://www.tiktok.com/@not-a-user/video/1", None),
+        ]);
+    }
+    
+    #[test]
+    fn mobile_hosts() {
+        check(&[
+            ("https://m.tiktok.com/v/6718335390845095173.html", video(None, "6718335390845095173")),
+            ("https://m.tiktok.com/@scout2015/video/6718335390845095173", video(Some("scout2015"), "6718335390845095173")),
+            ("https://m.tiktok.com/@scout2015", Some(TikTokLink::User("scout2015".to_string()))),
+            ("https://m.tiktok.com/v/6718335390845095173", None),
+            ("https://m.tiktok.com/v/abc.html", None),
+        ]);
+    }
+    
+    #[test]
+    fn trailing_query_and_fragment() {
+        check(&[
+            (
+                "https://www.tiktok.com/@scout2015/video/6718335390845095173?is_from_webapp=1&sender_device=pc&web_id=123",
+                video(Some("scout2015"), "6718335390845095173"),
+            ),
+            ("https://www.tiktok.com/@scout2015/video/6718335390845095173?lang=en#comments", video(Some("scout2015"), "6718335390845095173")),
+            ("https://www.tiktok.com/@scout2015?lang=en", Some(TikTokLink::User("scout2015".to_string()))),
+            ("https://www.tiktok.com/t/ZTRabc123/?_t=8abc&_r=1", short("https://www.tiktok.com/t/ZTRabc123/")),
+            ("https://vm.tiktok.com/ZMabc123/?keep=1", short("https://vm.tiktok.com/ZMabc123/?keep=1")),
+            ("https://www.tiktok.com/foryou?item_id=6718335390845095173", video(None, "6718335390845095173")),
+            ("https://www.tiktok.com/foryou?share_item_id=6718335390845095173&_r=1", video(None, "6718335390845095173")),
+            ("https://www.tiktok.com/foryou?item_id=abc", None),
+        ]);
+    }
+    
+    #[test]
+    fn other_pages() {
+        check(&[
+            ("https://www.tiktok.com/@scout2015/photo/7200000000000000000", Some(TikTokLink::Photo {
+                username: Some("scout2015".to_string()),
+                id: "7200000000000000000".to_string(),
+            })),
+            ("https://www.tiktok.com/@scout2015/live", Some(TikTokLink::Live("scout2015".to_string()))),
+            ("https://www.tiktok.com/@scout2015/playlist/Cats-7300000000000000000", Some(TikTokLink::Playlist {
+                username: "scout2015".to_string(),
+                id: "7300000000000000000".to_string(),
+            })),
+            ("https://www.tiktok.com/embed/v2/6718335390845095173", Some(TikTokLink::Embed("6718335390845095173".to_string()))),
+            ("https://www.tiktok.com/embed/6718335390845095173", Some(TikTokLink::Embed("6718335390845095173".to_string()))),
+            ("https://www.tiktok.com/tag/caf%C3%A9", Some(TikTokLink::Tag("café".to_string()))),
+            ("https://www.tiktok.com/tag/100%2525", Some(TikTokLink::Tag("100%25".to_string()))),
+            ("https://www.tiktok.com/discover/funny-cat-videos", Some(TikTokLink::Tag("funnycatvideos".to_string()))),
+            ("https://www.tiktok.com/discover/what's%20up?lang=en", Some(TikTokLink::Tag("whatsup".to_string()))),
+            ("https://www.tiktok.com/discover/---", None),
+            ("https://www.tiktok.com/music/original-sound-6718335390845095173", Some(TikTokLink::Music("original-sound-6718335390845095173".to_string()))),
+            ("https://www.tiktok.com/music/original%20sound-1", None),
+            ("snssdk1233://aweme/detail/6718335390845095173", video(None, "6718335390845095173")),
+            ("tiktok://challenge/detail/cats", Some(TikTokLink::Tag("cats".to_string()))),
+            ("tiktok://challenge/detail/caf%C3%A9", Some(TikTokLink::Tag("café".to_string()))),
+            // Deep links only carry the sound's ID, music pages need its title too
+            ("snssdk1233://music/detail/6718335390845095173", None),
+            ("https://www.tiktok.com/", None),
+            ("https://www.tiktok.com/explore", None),
+        ]);
+    }
+    
+    #[test]
+    fn rejects_look_alike_hosts() {
+        check(&[
+            ("https://tiktok.com.evil.example/@scout2015/video/6718335390845095173", None),
+            ("https://eviltiktok.com/@scout2015/video/6718335390845095173", None),
+            ("https://tiktok.co/@scout2015/video/6718335390845095173", None),
+            ("https://vm.tiktok.com.evil.example/ZMabc123/", None),
+            ("https://vmtiktok.com/ZMabc123/", None),
+            ("https://tiktok.com@evil.example/@scout2015", None),
+            ("https://evil.example/vm.tiktok.com/ZMabc123/", None),
+            ("https://evil.example/?url=https://www.tiktok.com/@scout2015", None),
+            ("https://evil.example\\@www.tiktok.com/@scout2015", None),
+            ("ftp://www.tiktok.com/@scout2015", None),
+            ("javascript://www.tiktok.com/%0aalert(1)", None),
+            ("not a link", None),
+            ("", None),
+        ]);
+    }
+}
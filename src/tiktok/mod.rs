@@ -1,3 +1,14 @@
+pub mod cache;
+pub mod challenge;
 pub mod client;
+pub mod coalesce;
+pub mod extract;
+pub mod link;
+pub mod media_policy;
 pub mod parser;
+pub mod proxy_pool;
+pub mod retry;
+pub mod schema;
+pub mod session;
+pub mod source;
 pub mod types;
@@ -1,148 +1,294 @@
-use regex::Regex;
+use scraper::Html;
 use serde_json::Value;
 
-use crate::error::AppError;
-use super::types::{UserInfo, VideoInfo, TagInfo};
+use super::extract::{self, FRONTITY_STATE, NEXT_DATA, STRATEGIES};
+use super::schema::{self, PageState};
+use super::types::{Extraction, PostImage, PostKind, UserInfo, VideoInfo, VideoPage, VideoSource, VideoVariant, TagInfo, MusicInfo};
 
-/// Extract SIGI_STATE JSON from TikTok HTML pages
-fn extract_sigi_state(html: &str) -> Option<Value> {
-    // TikTok embeds data in a script tag with id="SIGI_STATE" or __UNIVERSAL_DATA_FOR_REHYDRATION__
-    let patterns = [
-        r#"<script id="SIGI_STATE"[^>]*>([^<]+)</script>"#,
-        r#"<script id="__UNIVERSAL_DATA_FOR_REHYDRATION__"[^>]*>([^<]+)</script>"#,
-    ];
+/// Run `extract` over the page state of each strategy in turn, until one has
+/// what the page needs. Failures name every strategy that was tried and why.
+fn extract_with<T>(html: &str, extractor: &'static str, extract: impl Fn(PageState) -> Extraction<T>) -> Extraction<T> {
+    let document = Html::parse_document(html);
+    let mut reasons = Vec::new();
     
-    for pattern in patterns {
-        if let Ok(re) = Regex::new(pattern) {
-            if let Some(caps) = re.captures(html) {
-                if let Some(json_str) = caps.get(1) {
-                    if let Ok(json) = serde_json::from_str::<Value>(json_str.as_str()) {
-                        return Some(json);
+    for strategy in STRATEGIES {
+        let reason = match strategy.page_state(&document) {
+            None => continue,
+            Some(Ok(state)) => match extract(state) {
+                Extraction::Failed { reason, .. } => reason,
+                found => {
+                    if strategy != STRATEGIES[0] {
+                        tracing::debug!("{} extracted from {}", extractor, strategy.label());
                     }
+                    return found;
                 }
-            }
-        }
+            },
+            Some(Err(reason)) => reason,
+        };
+        reasons.push(format!("{}: {}", strategy.label(), reason));
     }
     
-    // Try finding JSON in script tags
-    let script_re = Regex::new(r#"<script[^>]*>(.*?)</script>"#).ok()?;
-    for caps in script_re.captures_iter(html) {
-        if let Some(content) = caps.get(1) {
-            let content = content.as_str();
-            if content.contains("\"UserModule\"") || content.contains("\"ItemModule\"") {
-                if let Ok(json) = serde_json::from_str::<Value>(content) {
-                    return Some(json);
-                }
-            }
+    if reasons.is_empty() {
+        return Extraction::failed(extractor, "no page data found");
+    }
+    Extraction::failed(extractor, reasons.join("; "))
+}
+
+pub fn parse_user_page(html: &str, username: &str) -> Extraction<UserInfo> {
+    extract_with(html, "user page", |state| parse_user_from_state(state, username))
+}
+
+fn parse_user_from_state(state: PageState, username: &str) -> Extraction<UserInfo> {
+    // Try __DEFAULT_SCOPE__ structure (newer)
+    if let Some(detail) = state.default_scope.and_then(|scope| scope.user_detail) {
+        if detail.is_unavailable() {
+            return Extraction::NotFound;
+        }
+        if let Some(user_info) = detail.user_info {
+            return user_from_schema(user_info.user, user_info.stats, username);
         }
     }
     
-    None
+    // Try UserModule structure (older)
+    let Some(mut user_module) = state.user_module else {
+        return Extraction::failed("user page", "no user details on the page");
+    };
+    let Some(user) = user_module.users.remove(username) else {
+        return Extraction::failed("user page", format!("no details for @{} on the page", username));
+    };
+    let stats = user_module.stats.remove(username);
+    user_from_schema(user, stats, username)
 }
 
-pub fn parse_user_page(html: &str, username: &str) -> Result<UserInfo, AppError> {
-    // Try to extract JSON data
-    if let Some(json) = extract_sigi_state(html) {
-        // Try different JSON structures TikTok uses
-        if let Some(user_info) = parse_user_from_json(&json, username) {
-            return Ok(user_info);
-        }
+fn user_from_schema(user: schema::User, stats: Option<schema::UserStats>, username: &str) -> Extraction<UserInfo> {
+    let mut missing = Vec::new();
+    if stats.is_none() {
+        missing.push("stats");
+    }
+    if user.avatar_larger.is_empty() && user.avatar_medium.is_empty() {
+        missing.push("avatar");
+    }
+    if user.sec_uid.is_empty() {
+        missing.push("videos");
     }
     
-    // Fallback: create placeholder user info
-    tracing::warn!("Could not parse TikTok JSON, using fallback for user: {}", username);
+    let stats = stats.unwrap_or_default();
+    let nickname = if user.nickname.is_empty() { username.to_string() } else { user.nickname };
+    // ld+json profiles may name the account nowhere but in the URL we asked for
+    let unique_id = if user.unique_id.is_empty() { username.to_string() } else { user.unique_id };
+    let avatar_url = if user.avatar_larger.is_empty() { user.avatar_medium } else { user.avatar_larger };
     
-    Ok(UserInfo {
-        id: "unknown".to_string(),
-        username: username.to_string(),
-        nickname: username.to_string(),
-        bio: "Profile information could not be loaded. TikTok may have changed their page structure.".to_string(),
-        avatar_url: String::new(),
-        follower_count: 0,
-        following_count: 0,
-        like_count: 0,
-        video_count: 0,
-        videos: vec![],
-    })
+    let user = UserInfo {
+        id: user.id,
+        username: unique_id,
+        nickname,
+        bio: user.signature,
+        avatar_url,
+        follower_count: stats.follower_count,
+        following_count: stats.following_count,
+        like_count: stats.heart_count.max(stats.heart),
+        video_count: stats.video_count,
+        sec_uid: user.sec_uid,
+        videos: vec![], // Filled from the item list endpoint
+        cursor: None,
+        has_more: false,
+        missing: Vec::new(),
+    };
+    Extraction::new(user, missing)
 }
 
-fn parse_user_from_json(json: &Value, username: &str) -> Option<UserInfo> {
-    // Try __DEFAULT_SCOPE__ structure (newer)
-    if let Some(scope) = json.get("__DEFAULT_SCOPE__") {
-        if let Some(user_detail) = scope.get("webapp.user-detail") {
-            if let Some(user_info) = user_detail.get("userInfo") {
-                return parse_user_info_object(user_info, username);
-            }
-        }
-    }
+pub fn parse_video_page(html: &str, video_id: &str) -> Extraction<VideoInfo> {
+    extract_with(html, "video page", |state| parse_video_from_state(state, video_id))
+}
+
+fn parse_video_from_state(state: PageState, video_id: &str) -> Extraction<VideoInfo> {
+    let found = |video: VideoInfo| {
+        let missing = video.missing_fields();
+        Extraction::new(video, missing)
+    };
     
-    // Try UserModule structure (older)
-    if let Some(user_module) = json.get("UserModule") {
-        if let Some(users) = user_module.get("users") {
-            if let Some(user) = users.get(username) {
-                return parse_legacy_user_object(user, username, json);
+    // Try __DEFAULT_SCOPE__ structure
+    if let Some(detail) = state.default_scope.and_then(|scope| scope.video_detail) {
+        if detail.is_unavailable() {
+            return Extraction::NotFound;
+        }
+        if let Some(item_info) = detail.item_info {
+            let mut video = video_from_item(item_info.item_struct);
+            if video.id.is_empty() {
+                video.id = video_id.to_string();
             }
+            return found(video);
         }
     }
     
-    None
+    // Try ItemModule structure
+    let Some(mut item_module) = state.item_module else {
+        return Extraction::failed("video page", "no video details on the page");
+    };
+    // Sometimes there's only one item
+    match item_module.remove(video_id).or_else(|| item_module.into_values().next()) {
+        Some(item) => found(video_from_item(item)),
+        None => Extraction::failed("video page", "no video details on the page"),
+    }
 }
 
-fn parse_user_info_object(user_info: &Value, username: &str) -> Option<UserInfo> {
-    let user = user_info.get("user")?;
-    let stats = user_info.get("stats").unwrap_or(&Value::Null);
+fn video_from_item(item: schema::Item) -> VideoInfo {
+    let first_non_empty = |candidates: &[&String]| {
+        candidates.iter().find(|url| !url.is_empty()).map(|url| url.to_string()).unwrap_or_default()
+    };
+    let video = &item.video;
+    let author = item.author;
+    let images = parse_post_images(item.image_post);
+    let kind = if images.is_empty() { PostKind::Video } else { PostKind::Photo };
     
-    Some(UserInfo {
-        id: user.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-        username: user.get("uniqueId").and_then(|v| v.as_str()).unwrap_or(username).to_string(),
-        nickname: user.get("nickname").and_then(|v| v.as_str()).unwrap_or(username).to_string(),
-        bio: user.get("signature").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-        avatar_url: user.get("avatarLarger").and_then(|v| v.as_str())
-            .or_else(|| user.get("avatarMedium").and_then(|v| v.as_str()))
-            .unwrap_or("").to_string(),
-        follower_count: stats.get("followerCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        following_count: stats.get("followingCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        like_count: stats.get("heartCount").and_then(|v| v.as_u64())
-            .or_else(|| stats.get("heart").and_then(|v| v.as_u64())).unwrap_or(0),
-        video_count: stats.get("videoCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        videos: vec![], // Videos would need separate parsing
-    })
+    let mut thumbnail_url = first_non_empty(&[&video.cover, &video.origin_cover, &video.dynamic_cover]);
+    if thumbnail_url.is_empty() {
+        thumbnail_url = images.first().map(|image| image.url.clone()).unwrap_or_default();
+    }
+    
+    VideoInfo {
+        video_url: first_non_empty(&[&video.play_addr, &video.download_addr]),
+        thumbnail_url,
+        variants: parse_video_variants(video),
+        kind,
+        images,
+        audio_url: item.music.as_ref().map(|m| m.play_url.clone()).unwrap_or_default(),
+        id: item.id,
+        description: item.desc,
+        // Left empty when absent so the fallbacks can fill them in
+        author_username: author.unique_id,
+        author_nickname: author.nickname,
+        author_avatar: author.avatar_medium,
+        like_count: item.stats.digg_count,
+        comment_count: item.stats.comment_count,
+        share_count: item.stats.share_count,
+        view_count: item.stats.play_count,
+        create_time: item.create_time as i64,
+        music_title: item.music.as_ref().map(|m| m.title.clone()).filter(|title| !title.is_empty()),
+        music_author: item.music.map(|m| m.author_name).filter(|author| !author.is_empty()),
+        source: VideoSource::Page,
+        missing: Vec::new(),
+    }
 }
 
-fn parse_legacy_user_object(user: &Value, username: &str, json: &Value) -> Option<UserInfo> {
-    let stats = json.get("UserModule")?.get("stats")?.get(username)?;
-    
-    Some(UserInfo {
-        id: user.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-        username: user.get("uniqueId").and_then(|v| v.as_str()).unwrap_or(username).to_string(),
-        nickname: user.get("nickname").and_then(|v| v.as_str()).unwrap_or(username).to_string(),
-        bio: user.get("signature").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-        avatar_url: user.get("avatarLarger").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-        follower_count: stats.get("followerCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        following_count: stats.get("followingCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        like_count: stats.get("heartCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        video_count: stats.get("videoCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        videos: vec![],
-    })
+/// Slides of a photo mode post, skipping any without a URL
+fn parse_post_images(image_post: Option<schema::ImagePost>) -> Vec<PostImage> {
+    image_post
+        .map(|post| post.images)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|image| {
+            Some(PostImage {
+                url: image.image_url.url_list.into_iter().next()?,
+                width: image.image_width as u32,
+                height: image.image_height as u32,
+            })
+        })
+        .collect()
 }
 
-pub fn parse_video_page(html: &str, video_id: &str) -> Result<VideoInfo, AppError> {
-    if let Some(json) = extract_sigi_state(html) {
-        if let Some(video) = parse_video_from_json(&json, video_id) {
-            return Ok(video);
+/// All encodings of an item's `video` object, in the order TikTok lists them
+fn parse_video_variants(video: &schema::Video) -> Vec<VideoVariant> {
+    let mut variants: Vec<VideoVariant> = Vec::new();
+    
+    for entry in &video.bitrate_info {
+        let Some(url) = entry.play_addr.url_list.first() else {
+            continue;
+        };
+        
+        variants.push(VideoVariant {
+            name: entry.gear_name.clone(),
+            url: url.clone(),
+            codec: entry.codec_type.clone(),
+            width: entry.play_addr.width as u32,
+            height: entry.play_addr.height as u32,
+            bitrate: entry.bitrate,
+            watermarked: false,
+        });
+    }
+    
+    for (url, watermarked) in [(&video.play_addr, false), (&video.download_addr, true)] {
+        if url.is_empty() || variants.iter().any(|variant| variant.url == *url) {
+            continue;
         }
+        
+        variants.push(VideoVariant {
+            name: if watermarked { "download" } else { "play" }.to_string(),
+            url: url.clone(),
+            codec: video.codec_type.clone(),
+            width: video.width as u32,
+            height: video.height as u32,
+            bitrate: video.bitrate,
+            watermarked,
+        });
     }
     
-    tracing::warn!("Could not parse TikTok JSON, using fallback for video: {}", video_id);
+    variants
+}
+
+/// Parse the `/embed/v2/{id}` player page, which has a simpler and more stable layout
+pub fn parse_embed_page(html: &str, video_id: &str) -> Option<VideoInfo> {
+    let document = Html::parse_document(html);
     
-    Ok(VideoInfo {
+    // Older embed pages use Frontity, newer ones Next.js
+    let next = extract::script_text(&document, &NEXT_DATA).map(|json| {
+        schema::decode::<schema::EmbedNextData>("embed page", &json).map(|next| next.props.page_props.video_data)
+    });
+    let frontity = extract::script_text(&document, &FRONTITY_STATE).map(|json| {
+        schema::decode::<schema::FrontityState>("embed page", &json)
+            .map(|state| state.source.data.into_values().find_map(|page| page.video_data))
+    });
+    
+    let video_data = [next, frontity].into_iter().flatten().find_map(|decoded| {
+        decoded.unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            None
+        })
+    })?;
+    
+    let item = video_data.item_infos;
+    let author = video_data.author_infos;
+    let music = video_data.music_infos;
+    let first = |urls: Vec<String>| urls.into_iter().next().unwrap_or_default();
+    
+    Some(VideoInfo {
+        id: if item.id.is_empty() { video_id.to_string() } else { item.id },
+        description: item.text,
+        author_username: author.unique_id,
+        author_nickname: author.nick_name,
+        author_avatar: first(author.covers),
+        video_url: first(item.video.urls),
+        thumbnail_url: first(item.covers),
+        like_count: item.digg_count,
+        comment_count: item.comment_count,
+        share_count: item.share_count,
+        view_count: item.play_count,
+        create_time: item.create_time as i64,
+        music_title: music.as_ref().and_then(|m| m.music_name.clone()),
+        music_author: music.and_then(|m| m.author_name),
+        variants: Vec::new(),
+        kind: PostKind::Video,
+        images: Vec::new(),
+        audio_url: String::new(),
+        source: VideoSource::Embed,
+        missing: Vec::new(),
+    })
+}
+
+/// Parse TikTok's oEmbed JSON. It only carries metadata and a cover, never the video itself.
+pub fn parse_oembed(json: &Value, video_id: &str) -> Option<VideoInfo> {
+    let oembed: schema::OEmbed = schema::decode_value("oEmbed", json)
+        .map_err(|e| tracing::warn!("{}", e))
+        .ok()?;
+    
+    Some(VideoInfo {
         id: video_id.to_string(),
-        description: "Video information could not be loaded.".to_string(),
-        author_username: "unknown".to_string(),
-        author_nickname: "Unknown".to_string(),
+        description: oembed.title,
+        author_username: oembed.author_unique_id,
+        author_nickname: oembed.author_name,
         author_avatar: String::new(),
         video_url: String::new(),
-        thumbnail_url: String::new(),
+        thumbnail_url: oembed.thumbnail_url,
         like_count: 0,
         comment_count: 0,
         share_count: 0,
@@ -150,118 +296,107 @@ pub fn parse_video_page(html: &str, video_id: &str) -> Result<VideoInfo, AppErro
         create_time: 0,
         music_title: None,
         music_author: None,
+        variants: Vec::new(),
+        kind: PostKind::Video,
+        images: Vec::new(),
+        audio_url: String::new(),
+        source: VideoSource::OEmbed,
+        missing: Vec::new(),
     })
 }
 
-fn parse_video_from_json(json: &Value, video_id: &str) -> Option<VideoInfo> {
-    // Try __DEFAULT_SCOPE__ structure
-    if let Some(scope) = json.get("__DEFAULT_SCOPE__") {
-        if let Some(video_detail) = scope.get("webapp.video-detail") {
-            if let Some(item_info) = video_detail.get("itemInfo") {
-                if let Some(item_struct) = item_info.get("itemStruct") {
-                    return parse_video_item(item_struct);
-                }
-            }
-        }
-    }
+/// Parse an item list API response (`/api/post/item_list/`, `/api/challenge/item_list/`)
+pub fn parse_item_list(json: &Value) -> Result<VideoPage, schema::SchemaError> {
+    let list: schema::ItemList = schema::decode_value("item list", json)?;
     
-    // Try ItemModule structure
-    if let Some(item_module) = json.get("ItemModule") {
-        if let Some(item) = item_module.get(video_id) {
-            return parse_video_item(item);
-        }
-        // Sometimes there's only one item
-        if let Some(items) = item_module.as_object() {
-            if let Some((_, item)) = items.iter().next() {
-                return parse_video_item(item);
+    // Skip items that don't match rather than losing the whole page
+    let videos = list.item_list
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| match schema::decode_value::<schema::Item>("item list entry", item) {
+            Ok(item) => Some(video_from_item(item)),
+            Err(e) => {
+                tracing::warn!("itemList[{}]: {}", index, e);
+                None
             }
-        }
-    }
+        })
+        .collect();
     
-    None
+    Ok(VideoPage {
+        videos,
+        cursor: list.cursor,
+        has_more: list.has_more,
+    })
 }
 
-fn parse_video_item(item: &Value) -> Option<VideoInfo> {
-    let author = item.get("author").unwrap_or(&Value::Null);
-    let stats = item.get("stats").unwrap_or(&Value::Null);
-    let video = item.get("video").unwrap_or(&Value::Null);
-    let music = item.get("music");
-    
-    // Get video URL - try multiple possible locations
-    let video_url = video.get("playAddr")
-        .or_else(|| video.get("downloadAddr"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-    
-    let thumbnail_url = video.get("cover")
-        .or_else(|| video.get("originCover"))
-        .or_else(|| video.get("dynamicCover"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-    
-    Some(VideoInfo {
-        id: item.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-        description: item.get("desc").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-        author_username: author.get("uniqueId").and_then(|v| v.as_str()).unwrap_or("unknown").to_string(),
-        author_nickname: author.get("nickname").and_then(|v| v.as_str()).unwrap_or("Unknown").to_string(),
-        author_avatar: author.get("avatarMedium").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-        video_url: video_url.to_string(),
-        thumbnail_url: thumbnail_url.to_string(),
-        like_count: stats.get("diggCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        comment_count: stats.get("commentCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        share_count: stats.get("shareCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        view_count: stats.get("playCount").and_then(|v| v.as_u64()).unwrap_or(0),
-        create_time: item.get("createTime").and_then(|v| v.as_i64()).unwrap_or(0),
-        music_title: music.and_then(|m| m.get("title")).and_then(|v| v.as_str()).map(String::from),
-        music_author: music.and_then(|m| m.get("authorName")).and_then(|v| v.as_str()).map(String::from),
-    })
+pub fn parse_tag_page(html: &str, tag_name: &str) -> Extraction<TagInfo> {
+    extract_with(html, "tag page", |state| parse_tag_from_state(state, tag_name))
 }
 
-pub fn parse_tag_page(html: &str, tag_name: &str) -> Result<TagInfo, AppError> {
-    if let Some(json) = extract_sigi_state(html) {
-        if let Some(tag) = parse_tag_from_json(&json, tag_name) {
-            return Ok(tag);
-        }
-    }
+fn parse_tag_from_state(state: PageState, tag_name: &str) -> Extraction<TagInfo> {
+    // Try __DEFAULT_SCOPE__ structure, then ChallengePage
+    let challenge_info = state.default_scope
+        .and_then(|scope| scope.challenge_detail?.challenge_info)
+        .or_else(|| state.challenge_page?.challenge_info);
+    let Some(challenge_info) = challenge_info else {
+        return Extraction::failed("tag page", "no hashtag details on the page");
+    };
+    let challenge = challenge_info.challenge;
     
-    tracing::warn!("Could not parse TikTok JSON, using fallback for tag: {}", tag_name);
+    let mut missing = Vec::new();
+    if challenge_info.stats.is_none() {
+        missing.push("view count");
+    }
+    if challenge.id.is_empty() {
+        missing.push("videos");
+    }
     
-    Ok(TagInfo {
-        name: tag_name.to_string(),
-        view_count: 0,
-        videos: vec![],
-    })
+    let tag = TagInfo {
+        id: challenge.id,
+        name: if challenge.title.is_empty() { tag_name.to_string() } else { challenge.title },
+        view_count: challenge_info.stats.map(|stats| stats.view_count).unwrap_or(0),
+        videos: vec![], // Filled from the item list endpoint
+        cursor: None,
+        has_more: false,
+        missing: Vec::new(),
+    };
+    Extraction::new(tag, missing)
 }
 
-fn parse_tag_from_json(json: &Value, tag_name: &str) -> Option<TagInfo> {
-    // Try __DEFAULT_SCOPE__ structure
-    if let Some(scope) = json.get("__DEFAULT_SCOPE__") {
-        if let Some(challenge_detail) = scope.get("webapp.challenge-detail") {
-            let challenge_info = challenge_detail.get("challengeInfo")?;
-            let challenge = challenge_info.get("challenge")?;
-            let stats = challenge_info.get("stats").unwrap_or(&Value::Null);
-            
-            return Some(TagInfo {
-                name: challenge.get("title").and_then(|v| v.as_str()).unwrap_or(tag_name).to_string(),
-                view_count: stats.get("viewCount").and_then(|v| v.as_u64()).unwrap_or(0),
-                videos: vec![], // Would need separate parsing
-            });
-        }
-    }
+pub fn parse_music_page(html: &str) -> Extraction<MusicInfo> {
+    extract_with(html, "music page", parse_music_from_state)
+}
+
+fn parse_music_from_state(state: PageState) -> Extraction<MusicInfo> {
+    // Try __DEFAULT_SCOPE__ structure, then MusicModule
+    let music_info = state.default_scope
+        .and_then(|scope| scope.music_detail?.music_info)
+        .or_else(|| state.music_module?.music_info);
+    let Some(music_info) = music_info else {
+        return Extraction::failed("music page", "no sound details on the page");
+    };
+    let music = music_info.music;
     
-    // Try ChallengePage structure
-    if let Some(challenge_page) = json.get("ChallengePage") {
-        if let Some(challenge_info) = challenge_page.get("challengeInfo") {
-            let challenge = challenge_info.get("challenge")?;
-            let stats = challenge_info.get("stats").unwrap_or(&Value::Null);
-            
-            return Some(TagInfo {
-                name: challenge.get("title").and_then(|v| v.as_str()).unwrap_or(tag_name).to_string(),
-                view_count: stats.get("viewCount").and_then(|v| v.as_u64()).unwrap_or(0),
-                videos: vec![],
-            });
-        }
+    let mut missing = Vec::new();
+    if music.title.is_empty() {
+        missing.push("title");
+    }
+    if music_info.stats.is_none() {
+        missing.push("video count");
+    }
+    if music.play_url.is_empty() {
+        missing.push("audio");
     }
     
-    None
+    let music = MusicInfo {
+        id: music.id,
+        title: music.title,
+        author: music.author_name,
+        cover_url: if music.cover_large.is_empty() { music.cover_medium } else { music.cover_large },
+        play_url: music.play_url,
+        original: music.original,
+        video_count: music_info.stats.map(|stats| stats.video_count).unwrap_or(0),
+        missing: Vec::new(),
+    };
+    Extraction::new(music, missing)
 }
@@ -0,0 +1,219 @@
+use rand::Rng;
+use reqwest::{Response, StatusCode};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::AppError;
+
+/// How often and how patiently transient upstream failures are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Full-jitter exponential backoff: random delay in `[0, base * 2^attempt]`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let millis = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+}
+
+#[derive(Debug, Default)]
+struct EndpointState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    /// A single request is let through once the cooldown has passed
+    probing: bool,
+}
+
+/// Per-endpoint circuit breaker. After `threshold` consecutive failures the
+/// endpoint is considered unhealthy and requests fail fast for `cooldown`.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    endpoints: Mutex<HashMap<&'static str, EndpointState>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            endpoints: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Returns the remaining cooldown if the endpoint is currently open
+    fn check(&self, endpoint: &'static str) -> Result<Call<'_>, Duration> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let state = endpoints.entry(endpoint).or_default();
+        let mut call = Call { breaker: self, endpoint, probe: false, finished: false };
+        
+        match state.open_until {
+            None => Ok(call),
+            Some(until) => {
+                let now = Instant::now();
+                if now < until {
+                    Err(until - now)
+                } else if state.probing {
+                    // Someone else is already testing the waters
+                    Err(Duration::from_secs(1))
+                } else {
+                    state.probing = true;
+                    call.probe = true;
+                    Ok(call)
+                }
+            }
+        }
+    }
+    
+    fn record_success(&self, endpoint: &'static str) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if let Some(state) = endpoints.get_mut(endpoint) {
+            if state.open_until.is_some() {
+                tracing::info!("Circuit closed for upstream endpoint: {}", endpoint);
+            }
+            *state = EndpointState::default();
+        }
+    }
+    
+    fn record_failure(&self, endpoint: &'static str) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let state = endpoints.entry(endpoint).or_default();
+        
+        state.consecutive_failures += 1;
+        state.probing = false;
+        
+        if state.consecutive_failures >= self.threshold {
+            if !matches!(state.open_until, Some(until) if until > Instant::now()) {
+                tracing::warn!(
+                    "Circuit opened for upstream endpoint {} after {} failures",
+                    endpoint,
+                    state.consecutive_failures
+                );
+            }
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+/// A request let through by the breaker, which must report how it went.
+/// A probe dropped without an outcome, e.g. because the client went away,
+/// hands the probe to the next request.
+struct Call<'a> {
+    breaker: &'a CircuitBreaker,
+    endpoint: &'static str,
+    probe: bool,
+    finished: bool,
+}
+
+impl Call<'_> {
+    fn success(mut self) {
+        self.finished = true;
+        self.breaker.record_success(self.endpoint);
+    }
+    
+    fn failure(mut self) {
+        self.finished = true;
+        self.breaker.record_failure(self.endpoint);
+    }
+}
+
+impl Drop for Call<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.finished {
+            if let Some(state) = self.breaker.endpoints.lock().unwrap().get_mut(self.endpoint) {
+                state.probing = false;
+            }
+        }
+    }
+}
+
+/// Send a request with retries on 429/5xx and transient network errors,
+/// guarded by the endpoint's circuit breaker. `send` is called per attempt.
+pub async fn send_with_retry<F, Fut>(
+    policy: &RetryPolicy,
+    breaker: &CircuitBreaker,
+    endpoint: &'static str,
+    send: F,
+) -> Result<Response, AppError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = reqwest::Result<Response>>,
+{
+    let call = match breaker.check(endpoint) {
+        Ok(call) => call,
+        Err(remaining) => return Err(AppError::RateLimited { retry_after: Some(remaining.as_secs().max(1)) }),
+    };
+    
+    let mut attempt = 0;
+    loop {
+        let (error, retry_after) = match send().await {
+            Ok(response) if is_transient(response.status()) => {
+                let retry_after = parse_retry_after(&response);
+                let error = if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                    AppError::RateLimited { retry_after: retry_after.map(|d| d.as_secs().max(1)) }
+                } else {
+                    AppError::FetchError(format!("Status: {}", response.status()))
+                };
+                (error, retry_after)
+            }
+            Ok(response) => {
+                call.success();
+                return Ok(response);
+            }
+            Err(e) if e.is_timeout() || e.is_connect() => (AppError::FetchError(e.to_string()), None),
+            Err(e) => {
+                call.failure();
+                return Err(AppError::FetchError(e.to_string()));
+            }
+        };
+        
+        // Don't sit on a request for longer than the upstream asks us to wait
+        let delay = retry_after.unwrap_or_else(|| policy.backoff(attempt));
+        if attempt >= policy.max_retries || delay > policy.max_delay {
+            call.failure();
+            return Err(error);
+        }
+        
+        tracing::debug!("Retrying {} in {:?} after: {}", endpoint, delay, error);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// `Retry-After` is either delay-seconds or an HTTP date
+fn parse_retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
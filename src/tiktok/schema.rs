@@ -0,0 +1,655 @@
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+/// A JSON document that didn't match its model
+#[derive(Error, Debug, Clone)]
+#[error("{what} JSON doesn't match at `{path}`: {message}")]
+pub struct SchemaError {
+    /// Which document, e.g. `user page`
+    pub what: &'static str,
+    pub path: String,
+    pub message: String,
+}
+
+impl SchemaError {
+    fn new(what: &'static str, error: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        Self {
+            what,
+            path: error.path().to_string(),
+            message: error.into_inner().to_string(),
+        }
+    }
+}
+
+/// Decode a JSON string, reporting where it stopped matching. Models ignore
+/// unknown fields and default most others, but the ones a page is useless
+/// without are required, so schema drift shows up in the logs instead of as zeros.
+pub fn decode<T: DeserializeOwned>(what: &'static str, json: &str) -> Result<T, SchemaError> {
+    let deserializer = &mut serde_json::Deserializer::from_str(json);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| SchemaError::new(what, e))
+}
+
+/// Decode an already parsed JSON value, reporting where it stopped matching
+pub fn decode_value<T: DeserializeOwned>(what: &'static str, json: &Value) -> Result<T, SchemaError> {
+    serde_path_to_error::deserialize(json).map_err(|e| SchemaError::new(what, e))
+}
+
+/// Page state from `__UNIVERSAL_DATA_FOR_REHYDRATION__` or the legacy `SIGI_STATE`.
+/// Only one of the two layouts is present on any page.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PageState {
+    #[serde(rename = "__DEFAULT_SCOPE__")]
+    pub default_scope: Option<DefaultScope>,
+    
+    // SIGI_STATE modules
+    #[serde(rename = "UserModule")]
+    pub user_module: Option<UserModule>,
+    #[serde(rename = "ItemModule")]
+    pub item_module: Option<HashMap<String, Item>>,
+    #[serde(rename = "ChallengePage")]
+    pub challenge_page: Option<ChallengeDetail>,
+    #[serde(rename = "MusicModule")]
+    pub music_module: Option<MusicDetail>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DefaultScope {
+    #[serde(rename = "webapp.user-detail")]
+    pub user_detail: Option<UserDetail>,
+    #[serde(rename = "webapp.video-detail")]
+    pub video_detail: Option<VideoDetail>,
+    #[serde(rename = "webapp.challenge-detail")]
+    pub challenge_detail: Option<ChallengeDetail>,
+    #[serde(rename = "webapp.music-detail")]
+    pub music_detail: Option<MusicDetail>,
+}
+
+/// TikTok's `statusCode` for accounts that don't exist, are banned or are private
+const USER_UNAVAILABLE: &[u64] = &[10202, 10221, 10222];
+
+/// `statusCode` for posts that don't exist or are private
+const VIDEO_UNAVAILABLE: &[u64] = &[10204, 10216];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UserDetail {
+    /// 0 when the account can be shown
+    #[serde(deserialize_with = "count")]
+    pub status_code: u64,
+    #[serde(deserialize_with = "non_empty")]
+    pub user_info: Option<UserInfo>,
+}
+
+impl UserDetail {
+    /// The page says the account isn't there to show, rather than that it failed
+    pub fn is_unavailable(&self) -> bool {
+        USER_UNAVAILABLE.contains(&self.status_code)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserInfo {
+    pub user: User,
+    pub stats: Option<UserStats>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    #[serde(default)]
+    pub id: String,
+    pub unique_id: String,
+    #[serde(default)]
+    pub nickname: String,
+    #[serde(default)]
+    pub signature: String,
+    #[serde(default)]
+    pub avatar_larger: String,
+    #[serde(default)]
+    pub avatar_medium: String,
+    #[serde(default)]
+    pub sec_uid: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UserStats {
+    #[serde(deserialize_with = "count")]
+    pub follower_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub following_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub heart_count: u64,
+    /// Older name of `heartCount`, still sent next to it on some pages
+    #[serde(deserialize_with = "count")]
+    pub heart: u64,
+    #[serde(deserialize_with = "count")]
+    pub video_count: u64,
+}
+
+/// Legacy SIGI_STATE users and stats, both keyed by username
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct UserModule {
+    pub users: HashMap<String, User>,
+    pub stats: HashMap<String, UserStats>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct VideoDetail {
+    /// 0 when the post can be shown
+    #[serde(deserialize_with = "count")]
+    pub status_code: u64,
+    #[serde(deserialize_with = "non_empty")]
+    pub item_info: Option<ItemInfo>,
+}
+
+impl VideoDetail {
+    /// The page says the post isn't there to show, rather than that it failed
+    pub fn is_unavailable(&self) -> bool {
+        VIDEO_UNAVAILABLE.contains(&self.status_code)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemInfo {
+    pub item_struct: Item,
+}
+
+/// One post, as found on video pages and in item list responses
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    pub id: String,
+    #[serde(default)]
+    pub desc: String,
+    #[serde(default, deserialize_with = "count")]
+    pub create_time: u64,
+    #[serde(default)]
+    pub author: Author,
+    #[serde(default)]
+    pub stats: ItemStats,
+    #[serde(default)]
+    pub video: Video,
+    pub music: Option<Music>,
+    /// Only on photo mode posts, whose `video` is empty
+    pub image_post: Option<ImagePost>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ImagePost {
+    pub images: Vec<PostImage>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PostImage {
+    #[serde(rename = "imageURL")]
+    pub image_url: UrlList,
+    #[serde(deserialize_with = "count")]
+    pub image_width: u64,
+    #[serde(deserialize_with = "count")]
+    pub image_height: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UrlList {
+    pub url_list: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Author {
+    pub unique_id: String,
+    pub nickname: String,
+    pub avatar_medium: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ItemStats {
+    #[serde(deserialize_with = "count")]
+    pub digg_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub comment_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub share_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub play_count: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Video {
+    pub play_addr: String,
+    pub download_addr: String,
+    pub cover: String,
+    pub origin_cover: String,
+    pub dynamic_cover: String,
+    #[serde(deserialize_with = "count")]
+    pub width: u64,
+    #[serde(deserialize_with = "count")]
+    pub height: u64,
+    #[serde(deserialize_with = "count")]
+    pub bitrate: u64,
+    pub codec_type: Option<String>,
+    pub bitrate_info: Vec<BitrateInfo>,
+}
+
+/// One encoding in `video.bitrateInfo`, the only part of the item in PascalCase
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct BitrateInfo {
+    pub gear_name: String,
+    #[serde(deserialize_with = "count")]
+    pub bitrate: u64,
+    pub codec_type: Option<String>,
+    pub play_addr: PlayAddr,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct PlayAddr {
+    pub url_list: Vec<String>,
+    #[serde(deserialize_with = "count")]
+    pub width: u64,
+    #[serde(deserialize_with = "count")]
+    pub height: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Music {
+    pub id: String,
+    pub title: String,
+    pub author_name: String,
+    pub cover_large: String,
+    pub cover_medium: String,
+    pub play_url: String,
+    pub original: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ChallengeDetail {
+    pub challenge_info: Option<ChallengeInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChallengeInfo {
+    pub challenge: Challenge,
+    pub stats: Option<ChallengeStats>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Challenge {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ChallengeStats {
+    #[serde(deserialize_with = "count")]
+    pub view_count: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MusicDetail {
+    pub music_info: Option<MusicInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MusicInfo {
+    pub music: Music,
+    pub stats: Option<MusicStats>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MusicStats {
+    #[serde(deserialize_with = "count")]
+    pub video_count: u64,
+}
+
+/// `__NEXT_DATA__` from the Next.js pages TikTok served before the rehydration script
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NextData {
+    pub props: NextProps,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NextProps {
+    pub page_props: NextPageProps,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NextPageProps {
+    #[serde(deserialize_with = "count")]
+    pub status_code: u64,
+    #[serde(deserialize_with = "non_empty")]
+    pub user_info: Option<UserInfo>,
+    #[serde(deserialize_with = "non_empty")]
+    pub item_info: Option<ItemInfo>,
+    pub challenge_info: Option<ChallengeInfo>,
+    pub music_info: Option<MusicInfo>,
+}
+
+impl From<NextData> for PageState {
+    /// Same objects as the rehydration scope, just somewhere else
+    fn from(next: NextData) -> Self {
+        let props = next.props.page_props;
+        
+        PageState {
+            default_scope: Some(DefaultScope {
+                user_detail: Some(UserDetail { status_code: props.status_code, user_info: props.user_info }),
+                video_detail: Some(VideoDetail { status_code: props.status_code, item_info: props.item_info }),
+                challenge_detail: Some(ChallengeDetail { challenge_info: props.challenge_info }),
+                music_detail: Some(MusicDetail { music_info: props.music_info }),
+            }),
+            ..PageState::default()
+        }
+    }
+}
+
+/// `__NEXT_DATA__` of the `/embed/v2/{id}` player page
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct EmbedNextData {
+    pub props: EmbedProps,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EmbedProps {
+    pub page_props: EmbedPageProps,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EmbedPageProps {
+    pub video_data: Option<EmbedVideoData>,
+}
+
+/// `__FRONTITY_CONNECT_STATE__` of older embed pages, one entry per page path
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FrontityState {
+    pub source: FrontitySource,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FrontitySource {
+    pub data: HashMap<String, FrontityPage>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FrontityPage {
+    pub video_data: Option<EmbedVideoData>,
+}
+
+/// The post on an embed page, in its own older layout
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbedVideoData {
+    pub item_infos: EmbedItem,
+    #[serde(default)]
+    pub author_infos: EmbedAuthor,
+    pub music_infos: Option<EmbedMusic>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EmbedItem {
+    pub id: String,
+    pub text: String,
+    pub video: EmbedVideo,
+    pub covers: Vec<String>,
+    #[serde(deserialize_with = "count")]
+    pub digg_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub comment_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub share_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub play_count: u64,
+    #[serde(deserialize_with = "count")]
+    pub create_time: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct EmbedVideo {
+    pub urls: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EmbedAuthor {
+    pub unique_id: String,
+    pub nick_name: String,
+    pub covers: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EmbedMusic {
+    pub music_name: Option<String>,
+    pub author_name: Option<String>,
+}
+
+/// A schema.org node from `<script type="application/ld+json">`, a `VideoObject`,
+/// `Person` or a page wrapping one of them
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LdNode {
+    #[serde(rename = "@type", deserialize_with = "one_or_many")]
+    pub kind: Vec<String>,
+    #[serde(rename = "@graph")]
+    pub graph: Vec<LdNode>,
+    pub main_entity: Option<Box<LdNode>>,
+    pub url: String,
+    pub name: String,
+    pub alternate_name: String,
+    pub description: String,
+    #[serde(deserialize_with = "one_or_many")]
+    pub image: Vec<LdUrl>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub thumbnail_url: Vec<LdUrl>,
+    pub content_url: String,
+    pub upload_date: String,
+    pub author: Option<Box<LdNode>>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub interaction_statistic: Vec<LdInteraction>,
+}
+
+/// Contents of one ld+json script, a single node or a list of them
+#[derive(Debug, Deserialize)]
+pub struct LdDocument(#[serde(deserialize_with = "one_or_many")] pub Vec<LdNode>);
+
+impl LdNode {
+    pub fn is(&self, kind: &str) -> bool {
+        self.kind.iter().any(|k| k == kind)
+    }
+}
+
+/// A URL, given either as a string or as an `ImageObject`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LdUrl {
+    Text(String),
+    Object { url: String },
+}
+
+impl LdUrl {
+    pub fn as_str(&self) -> &str {
+        match self {
+            LdUrl::Text(url) | LdUrl::Object { url } => url,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LdInteraction {
+    pub interaction_type: Option<LdType>,
+    #[serde(deserialize_with = "count")]
+    pub user_interaction_count: u64,
+}
+
+/// An action type, either a URL like `http://schema.org/LikeAction` or `{"@type": "LikeAction"}`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LdType {
+    Text(String),
+    Object {
+        #[serde(rename = "@type")]
+        kind: String,
+    },
+}
+
+impl LdType {
+    /// The bare type name, e.g. `LikeAction`
+    pub fn name(&self) -> &str {
+        match self {
+            LdType::Text(kind) | LdType::Object { kind } => kind.rsplit('/').next().unwrap_or(kind),
+        }
+    }
+}
+
+/// `/api/post/item_list/` and `/api/challenge/item_list/` responses. Items stay
+/// raw so one that doesn't match can be skipped without losing the page.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ItemList {
+    pub item_list: Vec<Value>,
+    #[serde(deserialize_with = "cursor")]
+    pub cursor: Option<String>,
+    pub has_more: bool,
+}
+
+/// `/oembed` response, only metadata and a cover
+#[derive(Debug, Deserialize)]
+pub struct OEmbed {
+    pub title: String,
+    #[serde(default)]
+    pub author_unique_id: String,
+    #[serde(default)]
+    pub author_name: String,
+    #[serde(default)]
+    pub thumbnail_url: String,
+}
+
+/// Numbers TikTok sends as integers, floats or strings depending on the page
+enum Number {
+    Integer(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NumberVisitor;
+        
+        impl de::Visitor<'_> for NumberVisitor {
+            type Value = Number;
+            
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number or a numeric string")
+            }
+            
+            fn visit_u64<E: de::Error>(self, n: u64) -> Result<Number, E> {
+                Ok(Number::Integer(n))
+            }
+            
+            fn visit_i64<E: de::Error>(self, n: i64) -> Result<Number, E> {
+                Ok(Number::Integer(n.max(0) as u64))
+            }
+            
+            fn visit_f64<E: de::Error>(self, n: f64) -> Result<Number, E> {
+                Ok(Number::Float(n))
+            }
+            
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Number, E> {
+                Ok(Number::Text(s.to_string()))
+            }
+        }
+        
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+/// Counts come as numbers, strings (`statsV2`, `createTime` on some pages) or
+/// occasionally floats. Anything else is a schema change worth reporting.
+fn count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match Option::<Number>::deserialize(deserializer)? {
+        None => Ok(0),
+        Some(Number::Integer(n)) => Ok(n),
+        Some(Number::Float(n)) => Ok(n.max(0.0) as u64),
+        Some(Number::Text(s)) => s
+            .parse()
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&s), &"a count")),
+    }
+}
+
+/// The cursor is sometimes a string, sometimes a number
+fn cursor<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Option::<Number>::deserialize(deserializer)? {
+        None => None,
+        Some(Number::Integer(n)) => Some(n.to_string()),
+        Some(Number::Float(n)) => Some(n.to_string()),
+        Some(Number::Text(s)) => Some(s),
+    })
+}
+
+/// Unavailable accounts and posts come with `{}` where their details would be
+fn non_empty<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Value::Object(fields)) if fields.is_empty() => Ok(None),
+        Some(value) => serde_path_to_error::deserialize(value)
+            .map(Some)
+            .map_err(|e| de::Error::custom(format!("{} at `{}`", e.inner(), e.path()))),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    Many(Vec<T>),
+    One(T),
+}
+
+/// schema.org properties may hold a single value or a list of them
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(match Option::<OneOrMany<T>>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(OneOrMany::Many(values)) => values,
+        Some(OneOrMany::One(value)) => vec![value],
+    })
+}
@@ -0,0 +1,245 @@
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use reqwest::Response;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use url::Url;
+
+use super::proxy_pool::ProxyPool;
+
+/// Cookies TikTok hands to browsers that it expects back on content requests
+const SESSION_COOKIES: &[&str] = &["ttwid", "msToken"];
+
+#[derive(Debug, Clone)]
+struct StoredCookie {
+    value: String,
+    expires: Option<SystemTime>,
+}
+
+/// One upstream browser-like session with its own cookie jar
+#[derive(Debug)]
+pub struct Session {
+    id: u64,
+    cookies: Mutex<HashMap<String, StoredCookie>>,
+    warmed_at: Mutex<Option<Instant>>,
+    /// Serializes warm-ups so concurrent requests don't all hit the home page
+    warm_lock: tokio::sync::Mutex<()>,
+    retired: AtomicBool,
+}
+
+impl Session {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            cookies: Mutex::new(HashMap::new()),
+            warmed_at: Mutex::new(None),
+            warm_lock: tokio::sync::Mutex::new(()),
+            retired: AtomicBool::new(false),
+        }
+    }
+    
+    /// Key for sticky proxy rotation, cookies are only valid from the IP that got them
+    pub fn key(&self) -> String {
+        format!("session-{}", self.id)
+    }
+    
+    /// `Cookie` header value with every unexpired cookie
+    pub fn cookie_header(&self) -> Option<String> {
+        let now = SystemTime::now();
+        let cookies = self.cookies.lock().unwrap();
+        let header = cookies
+            .iter()
+            .filter(|(_, cookie)| cookie.expires.is_none_or(|expires| expires > now))
+            .map(|(name, cookie)| format!("{}={}", name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        
+        if header.is_empty() { None } else { Some(header) }
+    }
+    
+    /// Remember `Set-Cookie` headers (and the `x-ms-token` header) from a response
+    pub fn store_cookies(&self, headers: &HeaderMap) {
+        let mut cookies = self.cookies.lock().unwrap();
+        
+        for value in headers.get_all(SET_COOKIE) {
+            if let Some((name, cookie)) = value.to_str().ok().and_then(parse_set_cookie) {
+                cookies.insert(name, cookie);
+            }
+        }
+        
+        if let Some(token) = headers.get("x-ms-token").and_then(|v| v.to_str().ok()) {
+            cookies.insert("msToken".to_string(), StoredCookie { value: token.to_string(), expires: None });
+        }
+    }
+    
+    /// Whether the session still needs a visit to the home page
+    fn needs_warm_up(&self, max_age: Duration) -> bool {
+        let fresh = self.warmed_at.lock().unwrap().is_some_and(|at| at.elapsed() < max_age);
+        if !fresh {
+            return true;
+        }
+        
+        let now = SystemTime::now();
+        let cookies = self.cookies.lock().unwrap();
+        // Only re-warm for cookies we had and lost, some regions never set msToken
+        cookies
+            .iter()
+            .any(|(name, cookie)| SESSION_COOKIES.contains(&name.as_str()) && cookie.expires.is_some_and(|e| e <= now))
+    }
+}
+
+/// Pool of independent upstream sessions, warmed up lazily and retired when
+/// TikTok starts challenging them.
+#[derive(Debug)]
+pub struct SessionManager {
+    sessions: Mutex<Vec<Arc<Session>>>,
+    base_url: String,
+    pool: Arc<ProxyPool>,
+    max_age: Duration,
+    next: AtomicUsize,
+    next_id: AtomicU64,
+}
+
+impl SessionManager {
+    /// `size` of zero disables sessions entirely and requests go out cookieless
+    pub fn new(size: usize, base_url: &str, pool: Arc<ProxyPool>, max_age: Duration) -> Self {
+        let sessions = (0..size as u64).map(|id| Arc::new(Session::new(id))).collect();
+        
+        Self {
+            sessions: Mutex::new(sessions),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            pool,
+            max_age,
+            next: AtomicUsize::new(0),
+            next_id: AtomicU64::new(size as u64),
+        }
+    }
+    
+    /// Next session in round-robin order, warmed up if its cookies are missing or expired
+    pub async fn acquire(&self) -> Option<Arc<Session>> {
+        let session = {
+            let sessions = self.sessions.lock().unwrap();
+            if sessions.is_empty() {
+                return None;
+            }
+            let index = self.next.fetch_add(1, Ordering::Relaxed) % sessions.len();
+            Arc::clone(&sessions[index])
+        };
+        
+        if session.needs_warm_up(self.max_age) {
+            let _guard = session.warm_lock.lock().await;
+            // Another request may have finished warming it while we waited
+            if session.needs_warm_up(self.max_age) {
+                self.warm_up(&session).await;
+            }
+        }
+        
+        Some(session)
+    }
+    
+    /// Visit the home page to pick up `ttwid`/`msToken`
+    async fn warm_up(&self, session: &Session) {
+        let url = format!("{}/", self.base_url);
+        let result = self.pool
+            .get(
+                &url,
+                Some(&session.key()),
+                |request, hop| {
+                    let request = request
+                        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
+                        .header("Accept-Language", "en-US,en;q=0.9");
+                    match self.cookie_header_for(session, hop) {
+                        Some(cookies) => request.header(reqwest::header::COOKIE, cookies),
+                        None => request,
+                    }
+                },
+                |response| self.store_cookies_from(session, response),
+                |_| true,
+            )
+            .await;
+        
+        match result {
+            Ok(_) => tracing::debug!("Warmed up upstream session {}", session.id),
+            Err(e) => tracing::warn!("Could not warm up upstream session {}: {}", session.id, e),
+        }
+        
+        // Even a failed warm-up waits for `max_age`, so a blocked home page isn't hammered
+        *session.warmed_at.lock().unwrap() = Some(Instant::now());
+    }
+    
+    /// `Cookie` header for a request to `url`, if it's one that may see them
+    pub fn cookie_header_for(&self, session: &Session, url: &str) -> Option<String> {
+        if !self.shares_cookies(url) {
+            return None;
+        }
+        session.cookie_header()
+    }
+    
+    /// Keep the cookies a response set, unless it came from a host that
+    /// doesn't share them
+    pub fn store_cookies_from(&self, session: &Session, response: &Response) {
+        if self.shares_cookies(response.url().as_str()) {
+            session.store_cookies(response.headers());
+        }
+    }
+    
+    /// Redirects can lead anywhere, cookies only go to TikTok itself, or to the
+    /// upstream when `base_url` points at a mirror
+    fn shares_cookies(&self, url: &str) -> bool {
+        let Some(url) = Url::parse(url).ok().filter(|url| url.scheme() == "https" || url.scheme() == "http") else {
+            return false;
+        };
+        let Some(host) = url.host_str().map(|host| host.trim_end_matches('.').to_ascii_lowercase()) else {
+            return false;
+        };
+        if host == "tiktok.com" || host.ends_with(".tiktok.com") {
+            return true;
+        }
+        
+        Url::parse(&self.base_url).is_ok_and(|base| {
+            base.host_str() == Some(host.as_str()) && base.port_or_known_default() == url.port_or_known_default()
+        })
+    }
+    
+    /// Replace a session TikTok has flagged with a fresh one
+    pub fn retire(&self, session: &Session) {
+        if session.retired.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(slot) = sessions.iter_mut().find(|s| s.id == session.id) {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!("Retiring upstream session {}, replaced by {}", session.id, id);
+            *slot = Arc::new(Session::new(id));
+        }
+    }
+}
+
+/// Parse `name=value; Expires=...; Max-Age=...`, ignoring the other attributes
+fn parse_set_cookie(header: &str) -> Option<(String, StoredCookie)> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.trim().split_once('=')?;
+    let mut expires = None;
+    
+    for attribute in parts {
+        let Some((key, val)) = attribute.trim().split_once('=') else {
+            continue;
+        };
+        match key.to_ascii_lowercase().as_str() {
+            // Max-Age wins over Expires
+            "max-age" => {
+                if let Ok(secs) = val.trim().parse::<i64>() {
+                    expires = Some(SystemTime::now() + Duration::from_secs(secs.max(0) as u64));
+                    break;
+                }
+            }
+            "expires" => expires = httpdate::parse_http_date(val.trim()).ok(),
+            _ => {}
+        }
+    }
+    
+    Some((name.trim().to_string(), StoredCookie { value: value.trim().to_string(), expires }))
+}
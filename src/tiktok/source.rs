@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+use url::Url;
+
+use crate::error::AppError;
+use super::types::{UserInfo, VideoInfo, TagInfo, MusicInfo};
+
+/// Where TikTok data comes from. Route handlers only talk to this trait,
+/// so backends can be swapped or mocked without touching them.
+#[async_trait]
+pub trait TikTokSource: Send + Sync {
+    /// Fetch user profile and one page of videos, starting at `cursor`
+    async fn fetch_user(&self, username: &str, cursor: Option<&str>) -> Result<UserInfo, AppError>;
+    
+    /// Fetch just the user profile, without the item list request for their videos
+    async fn fetch_profile(&self, username: &str) -> Result<UserInfo, AppError>;
+    
+    /// Fetch single video
+    async fn fetch_video(&self, video_id: &str) -> Result<VideoInfo, AppError>;
+    
+    /// Fetch tag/hashtag info and one page of videos, starting at `cursor`
+    async fn fetch_tag(&self, tag_name: &str, cursor: Option<&str>) -> Result<TagInfo, AppError>;
+    
+    /// Fetch sound/music page, `slug` is the `title-id` path segment TikTok uses
+    async fn fetch_music(&self, slug: &str) -> Result<MusicInfo, AppError>;
+    
+    /// Follow a short link (vm.tiktok.com, /t/...) and return the URL it lands on
+    async fn resolve_short_link(&self, url: &Url) -> Result<Url, AppError>;
+    
+    /// Drop any cached copy of a video, e.g. after the CDN rejected its media URLs
+    fn invalidate_video(&self, _video_id: &str) {}
+    
+    /// Drop any cached copy of a user's profile and first page
+    fn invalidate_user(&self, _username: &str) {}
+}
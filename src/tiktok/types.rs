@@ -1,5 +1,67 @@
 use serde::{Deserialize, Serialize};
 
+use crate::error::AppError;
+use crate::signing;
+
+/// Outcome of running one extractor over a TikTok response
+#[derive(Debug)]
+pub enum Extraction<T> {
+    Complete(T),
+    /// Usable, but these fields couldn't be found
+    Partial(T, Vec<&'static str>),
+    /// TikTok says there's nothing to show, e.g. a deleted or private account
+    NotFound,
+    Failed { extractor: &'static str, reason: String },
+}
+
+impl<T> Extraction<T> {
+    /// Complete unless something is listed as missing
+    pub fn new(value: T, missing: Vec<&'static str>) -> Self {
+        if missing.is_empty() {
+            Extraction::Complete(value)
+        } else {
+            Extraction::Partial(value, missing)
+        }
+    }
+    
+    pub fn failed(extractor: &'static str, reason: impl Into<String>) -> Self {
+        Extraction::Failed { extractor, reason: reason.into() }
+    }
+}
+
+impl<T: Degradable> Extraction<T> {
+    /// The value with its gaps recorded for the degraded-data banner, or an
+    /// error to show instead of a page full of made-up data
+    pub fn into_result(self) -> Result<T, AppError> {
+        match self {
+            Extraction::Complete(value) => Ok(value),
+            Extraction::Partial(mut value, missing) => {
+                value.mark_missing(&missing);
+                Ok(value)
+            }
+            Extraction::NotFound => Err(AppError::NotFound),
+            Extraction::Failed { extractor, reason } => {
+                tracing::warn!("Could not extract {}: {}", extractor, reason);
+                Err(AppError::ExtractionFailed { extractor, reason })
+            }
+        }
+    }
+}
+
+/// Page data that can be shown with some fields missing
+pub trait Degradable {
+    fn missing_mut(&mut self) -> &mut Vec<String>;
+    
+    fn mark_missing(&mut self, fields: &[&str]) {
+        let missing = self.missing_mut();
+        for field in fields {
+            if !missing.iter().any(|m| m == field) {
+                missing.push(field.to_string());
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserInfo {
     pub id: String,
@@ -11,7 +73,13 @@ pub struct UserInfo {
     pub following_count: u64,
     pub like_count: u64,
     pub video_count: u64,
+    pub sec_uid: String,
     pub videos: Vec<VideoInfo>,
+    /// Cursor for the next (older) page of videos
+    pub cursor: Option<String>,
+    pub has_more: bool,
+    /// Fields TikTok didn't give us, shown in a banner
+    pub missing: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,30 +98,356 @@ pub struct VideoInfo {
     pub create_time: i64,
     pub music_title: Option<String>,
     pub music_author: Option<String>,
+    /// Every encoding the item JSON offers, for downloads
+    pub variants: Vec<VideoVariant>,
+    pub kind: PostKind,
+    /// Slides of a photo post, in order
+    pub images: Vec<PostImage>,
+    /// The post's sound, which plays under a photo slideshow
+    pub audio_url: String,
+    /// Which extractor produced this data
+    pub source: VideoSource,
+    /// Fields no extractor could find, shown in a banner
+    pub missing: Vec<String>,
+}
+
+/// What a post shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PostKind {
+    #[default]
+    Video,
+    /// Photo mode, a slideshow of images over a sound
+    Photo,
+}
+
+/// One image of a photo post
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostImage {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A carousel slide, with its neighbours for the prev/next links
+#[derive(Debug, Clone)]
+pub struct Slide {
+    /// 1-based, used as the `#slide-N` anchor
+    pub number: usize,
+    pub url: String,
+    pub previous: usize,
+    pub next: usize,
+}
+
+/// One encoding of a video: `playAddr`, `downloadAddr` or a `bitrateInfo` entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoVariant {
+    /// TikTok's gear name such as `normal_720_0`, or `play`/`download`
+    pub name: String,
+    pub url: String,
+    pub codec: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// Bits per second, 0 when unknown
+    pub bitrate: u64,
+    /// `downloadAddr` carries TikTok's watermark, the others don't
+    pub watermarked: bool,
+}
+
+impl VideoVariant {
+    pub fn is_h264(&self) -> bool {
+        self.codec.as_deref().is_none_or(|codec| codec.starts_with("h264"))
+    }
+    
+    /// Human readable summary for the download menu, e.g. `720p H.264 1.2 Mbps`
+    pub fn label(&self) -> String {
+        let mut parts = Vec::new();
+        
+        if self.height > 0 {
+            parts.push(format!("{}p", self.width.min(self.height)));
+        }
+        if let Some(codec) = &self.codec {
+            parts.push(if self.is_h264() { "H.264".to_string() } else if codec.contains("265") || codec.contains("bytevc1") { "H.265".to_string() } else { codec.clone() });
+        }
+        if self.bitrate > 0 {
+            parts.push(format!("{:.1} Mbps", self.bitrate as f64 / 1_000_000.0));
+        }
+        if parts.is_empty() {
+            parts.push(self.name.clone());
+        }
+        if self.watermarked {
+            parts.push("(watermarked)".to_string());
+        }
+        
+        parts.join(" ")
+    }
+}
+
+/// Extractors tried for a video page, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoSource {
+    /// The regular `/video/{id}` page
+    Page,
+    /// The `/embed/v2/{id}` player page
+    Embed,
+    /// The oEmbed JSON endpoint, metadata only
+    OEmbed,
+}
+
+impl VideoSource {
+    /// Whether a fallback extractor had to step in
+    pub fn is_fallback(&self) -> bool {
+        matches!(self, VideoSource::Embed | VideoSource::OEmbed)
+    }
+    
+    pub fn label(&self) -> &'static str {
+        match self {
+            VideoSource::Page => "video page",
+            VideoSource::Embed => "embed page",
+            VideoSource::OEmbed => "oEmbed",
+        }
+    }
 }
 
 impl VideoInfo {
-    /// Get proxied video URL
-    pub fn proxied_video_url(&self) -> String {
-        format!("/proxy?url={}", urlencoding::encode(&self.video_url))
+    /// Whether all fields the video page needs are present
+    pub fn is_complete(&self) -> bool {
+        self.missing_fields().is_empty()
+    }
+    
+    pub fn is_photo(&self) -> bool {
+        self.kind == PostKind::Photo
+    }
+    
+    /// Fields the video page needs that are still empty
+    pub fn missing_fields(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.is_photo() && self.images.is_empty() {
+            missing.push("photos");
+        }
+        if !self.is_photo() && self.video_url.is_empty() {
+            missing.push("video");
+        }
+        if self.thumbnail_url.is_empty() {
+            missing.push("cover");
+        }
+        if self.author_username.is_empty() {
+            missing.push("author");
+        }
+        missing
+    }
+    
+    /// Fill empty fields from another extractor's result, keeping our `source`
+    pub fn fill_missing(&mut self, other: VideoInfo) {
+        fn fill(field: &mut String, other: String) {
+            if field.is_empty() {
+                *field = other;
+            }
+        }
+        fn fill_count(field: &mut u64, other: u64) {
+            if *field == 0 {
+                *field = other;
+            }
+        }
+        
+        fill(&mut self.description, other.description);
+        fill(&mut self.author_username, other.author_username);
+        fill(&mut self.author_nickname, other.author_nickname);
+        fill(&mut self.author_avatar, other.author_avatar);
+        fill(&mut self.video_url, other.video_url);
+        fill(&mut self.thumbnail_url, other.thumbnail_url);
+        fill_count(&mut self.like_count, other.like_count);
+        fill_count(&mut self.comment_count, other.comment_count);
+        fill_count(&mut self.share_count, other.share_count);
+        fill_count(&mut self.view_count, other.view_count);
+        if self.create_time == 0 {
+            self.create_time = other.create_time;
+        }
+        self.music_title = self.music_title.take().or(other.music_title);
+        self.music_author = self.music_author.take().or(other.music_author);
+        if self.variants.is_empty() {
+            self.variants = other.variants;
+        }
+        if self.images.is_empty() && !other.images.is_empty() {
+            self.kind = other.kind;
+            self.images = other.images;
+        }
+        fill(&mut self.audio_url, other.audio_url);
+    }
+    
+    /// Variant the download button serves: unwatermarked first, then H.264 for
+    /// compatibility, then the highest resolution and bitrate
+    pub fn preferred_variant(&self) -> Option<&VideoVariant> {
+        self.variants
+            .iter()
+            .max_by_key(|v| (!v.watermarked, v.is_h264(), v.width.min(v.height), v.bitrate))
+    }
+    
+    /// `Content-Disposition` filename for downloads, `author_id.mp4`
+    pub fn download_filename(&self) -> String {
+        format!("{}.mp4", self.download_stem())
     }
     
-    /// Get proxied thumbnail URL  
-    pub fn proxied_thumbnail_url(&self) -> String {
-        format!("/proxy?url={}", urlencoding::encode(&self.thumbnail_url))
+    /// `author_id`, safe to put in a filename
+    pub fn download_stem(&self) -> String {
+        let author: String = self.author_username
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+            .collect();
+        let author = if author.is_empty() { "tiktok".to_string() } else { author };
+        
+        format!("{}_{}", author, self.id)
+    }
+    
+    /// Download link for the preferred variant
+    pub fn download_url(&self) -> String {
+        format!("/media/download/{}?sig={}", self.id, self.media_signature("download"))
+    }
+    
+    /// Download link and label for every variant, for the quality menu
+    pub fn download_options(&self) -> Vec<(String, String)> {
+        let signature = self.media_signature("download");
+        self.variants
+            .iter()
+            .enumerate()
+            .map(|(index, variant)| {
+                (format!("/media/download/{}?variant={}&sig={}", self.id, index, signature), variant.label())
+            })
+            .collect()
+    }
+    
+    /// Thumbnail shrunk to the size of a video grid card
+    pub fn proxied_grid_thumbnail_url(&self) -> String {
+        format!("{}&w=360", signing::proxy_url(&self.thumbnail_url))
+    }
+    
+    /// Get proxied author avatar URL, sized for the small avatar next to the name
+    pub fn proxied_author_avatar_url(&self) -> String {
+        format!("{}&w=96", signing::proxy_url(&self.author_avatar))
+    }
+    
+    /// Link to the video that outlives the CDN URL, for bookmarks and downloads
+    pub fn media_video_url(&self) -> String {
+        format!("/media/video/{}?sig={}", self.id, self.media_signature("video"))
+    }
+    
+    /// Link to the cover image that outlives the CDN URL
+    pub fn media_cover_url(&self) -> String {
+        format!("/media/cover/{}?sig={}", self.id, self.media_signature("cover"))
+    }
+    
+    /// Link to the sound of a photo post that outlives the CDN URL
+    pub fn media_audio_url(&self) -> String {
+        format!("/media/audio/{}?sig={}", self.id, self.media_signature("audio"))
+    }
+    
+    /// Photo post slides with stable image links, wrapping around at both ends
+    pub fn slides(&self) -> Vec<Slide> {
+        let count = self.images.len();
+        let signature = self.media_signature("image");
+        
+        (1..=count)
+            .map(|number| Slide {
+                number,
+                url: format!("/media/image/{}/{}?sig={}", self.id, number - 1, signature),
+                previous: if number == 1 { count } else { number - 1 },
+                next: if number == count { 1 } else { number + 1 },
+            })
+            .collect()
+    }
+    
+    /// Zip of every photo in a photo post
+    pub fn images_download_url(&self) -> String {
+        format!("/media/download/{}/images?sig={}", self.id, self.media_signature("images"))
+    }
+    
+    fn media_signature(&self, kind: &str) -> String {
+        signing::media_signature(kind, &self.id)
     }
 }
 
 impl UserInfo {
-    /// Get proxied avatar URL
-    pub fn proxied_avatar_url(&self) -> String {
-        format!("/proxy?url={}", urlencoding::encode(&self.avatar_url))
+    /// Whether the follower and like counts are real rather than zeroes standing in
+    pub fn has_stats(&self) -> bool {
+        !self.missing.iter().any(|field| field == "stats")
+    }
+    
+    /// Link to the avatar that outlives the CDN URL
+    pub fn media_avatar_url(&self) -> String {
+        format!(
+            "/media/avatar/{}?sig={}",
+            urlencoding::encode(&self.username),
+            signing::media_signature("avatar", &self.username)
+        )
     }
 }
 
+/// One page of an item list endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VideoPage {
+    pub videos: Vec<VideoInfo>,
+    pub cursor: Option<String>,
+    pub has_more: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TagInfo {
+    pub id: String,
     pub name: String,
     pub view_count: u64,
     pub videos: Vec<VideoInfo>,
+    /// Cursor for the next page of videos
+    pub cursor: Option<String>,
+    pub has_more: bool,
+    /// Fields TikTok didn't give us, shown in a banner
+    pub missing: Vec<String>,
+}
+
+impl Degradable for UserInfo {
+    fn missing_mut(&mut self) -> &mut Vec<String> {
+        &mut self.missing
+    }
+}
+
+impl Degradable for VideoInfo {
+    fn missing_mut(&mut self) -> &mut Vec<String> {
+        &mut self.missing
+    }
+}
+
+impl Degradable for TagInfo {
+    fn missing_mut(&mut self) -> &mut Vec<String> {
+        &mut self.missing
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MusicInfo {
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub cover_url: String,
+    pub play_url: String,
+    pub original: bool,
+    pub video_count: u64,
+    /// Fields the page didn't have, shown in a banner
+    #[serde(default)]
+    pub missing: Vec<String>,
+}
+
+impl Degradable for MusicInfo {
+    fn missing_mut(&mut self) -> &mut Vec<String> {
+        &mut self.missing
+    }
+}
+
+impl MusicInfo {
+    /// Get proxied cover URL
+    pub fn proxied_cover_url(&self) -> String {
+        format!("{}&w=200", signing::proxy_url(&self.cover_url))
+    }
+    
+    /// Get proxied audio URL
+    pub fn proxied_play_url(&self) -> String {
+        signing::proxy_url(&self.play_url)
+    }
 }
@@ -0,0 +1,89 @@
+use axum::body::Bytes;
+use std::io;
+
+/// 1980-01-01 in MS-DOS date format, the earliest a zip entry can carry
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// Names are UTF-8
+const FLAGS: u16 = 1 << 11;
+
+/// Writes an uncompressed zip archive one entry at a time, so it can be sent
+/// as it's built. Each entry is whole in memory and the archive stays under
+/// 4 GiB, there's no zip64.
+#[derive(Default)]
+pub struct ZipStream {
+    /// Bytes handed out so far, where the next local header starts
+    offset: u64,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipStream {
+    /// Local header and data of the next entry
+    pub fn entry(&mut self, name: &str, data: Bytes) -> io::Result<[Bytes; 2]> {
+        let crc = crc32fast::hash(&data);
+        let size = fits_u32(data.len() as u64)?;
+        let name_len = u16::try_from(name.len()).map_err(|_| io::Error::other("zip entry name is too long"))?;
+        let header_offset = fits_u32(self.offset)?;
+        
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes());
+        header.extend_from_slice(&FLAGS.to_le_bytes());
+        // Stored, then modification time and date
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&DOS_DATE.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        
+        let central = &mut self.central_directory;
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        // Made by and needed to extract
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&FLAGS.to_le_bytes());
+        central.extend_from_slice(&0u16.to_le_bytes());
+        central.extend_from_slice(&0u16.to_le_bytes());
+        central.extend_from_slice(&DOS_DATE.to_le_bytes());
+        central.extend_from_slice(&crc.to_le_bytes());
+        central.extend_from_slice(&size.to_le_bytes());
+        central.extend_from_slice(&size.to_le_bytes());
+        central.extend_from_slice(&name_len.to_le_bytes());
+        // Extra field, comment, disk number, internal and external attributes
+        central.extend_from_slice(&[0; 12]);
+        central.extend_from_slice(&header_offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+        
+        self.offset += (header.len() + data.len()) as u64;
+        self.entries = self.entries.checked_add(1).ok_or_else(|| io::Error::other("too many zip entries"))?;
+        
+        Ok([Bytes::from(header), data])
+    }
+    
+    /// Central directory and end record, the last bytes of the archive
+    pub fn finish(self) -> io::Result<Bytes> {
+        let directory_offset = fits_u32(self.offset)?;
+        let directory_size = fits_u32(self.central_directory.len() as u64)?;
+        
+        let mut end = self.central_directory;
+        end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        // This disk and the one the directory starts on
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&directory_size.to_le_bytes());
+        end.extend_from_slice(&directory_offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        
+        Ok(Bytes::from(end))
+    }
+}
+
+fn fits_u32(value: u64) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| io::Error::other("zip archive is too large"))
+}